@@ -0,0 +1,280 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const MAX_HEADER_SIZE: u64 = 16 * 1024;
+// Connections are served one at a time, so a slow client only holds the rest up this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+    reply: mpsc::Sender<(u16, String)>,
+}
+
+impl Request {
+    pub fn respond(self, status: u16, body: String) {
+        let _ = self.reply.send((status, body));
+    }
+}
+
+pub struct Server {
+    requests: mpsc::Receiver<Request>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn start(port: u16, token: String, wake: impl Fn() + Send + 'static) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        let (sender, requests) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = handle_connection(stream, &token, &sender, &wake) {
+                            eprintln!("Failed to handle API request: {}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                    Err(e) => {
+                        eprintln!("Failed to accept API connection: {}", e);
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            }
+        });
+        Ok(Self { requests, stop, thread: Some(thread) })
+    }
+
+    pub fn try_recv(&self) -> Option<Request> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Gives up once the connection's time is used, however slowly the bytes trickle in.
+struct Deadline {
+    stream: TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.until.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+type RequestReader = BufReader<std::io::Take<Deadline>>;
+// The method, path and body, or the status and message a request is refused with.
+type ParsedRequest = Result<(String, String, String), (u16, &'static str)>;
+
+fn handle_connection(
+    stream: TcpStream,
+    token: &str,
+    sender: &mpsc::Sender<Request>,
+    wake: &impl Fn(),
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(REPLY_TIMEOUT))?;
+    let deadline = Deadline { stream: stream.try_clone()?, until: Instant::now() + REQUEST_TIMEOUT };
+    let mut reader = BufReader::new(deadline.take(MAX_HEADER_SIZE));
+
+    let (method, path, body) = match read_request(&mut reader, token) {
+        Ok(Ok(request)) => request,
+        Ok(Err((status, message))) => return write_response(stream, status, &error_body(message)),
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => {
+            return write_response(stream, 408, &error_body("Request took too long to arrive"));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let (reply, replies) = mpsc::channel();
+    if sender.send(Request { method, path, body, reply }).is_err() {
+        return write_response(stream, 503, &error_body("Notes is shutting down"));
+    }
+    wake();
+    match replies.recv_timeout(REPLY_TIMEOUT) {
+        Ok((status, body)) => write_response(stream, status, &body),
+        Err(_) => write_response(stream, 503, &error_body("Notes did not respond in time")),
+    }
+}
+
+fn read_request(reader: &mut RequestReader, token: &str) -> std::io::Result<ParsedRequest> {
+    let Some(request_line) = read_header_line(reader)? else {
+        return Ok(Err((431, "Request headers too large")));
+    };
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+        _ => return Ok(Err((400, "Malformed request line"))),
+    };
+
+    let mut content_length = 0;
+    let mut authorized = false;
+    loop {
+        let Some(line) = read_header_line(reader)? else {
+            return Ok(Err((431, "Request headers too large")));
+        };
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap_or(0),
+                "authorization" => authorized = value.strip_prefix("Bearer ").is_some_and(|t| t.trim() == token),
+                _ => {}
+            }
+        }
+    }
+
+    if !authorized {
+        return Ok(Err((401, "Missing or invalid token")));
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Err((413, "Request body too large")));
+    }
+    reader.get_mut().set_limit(content_length as u64);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    match String::from_utf8(body) {
+        Ok(body) => Ok(Ok((method, path, body))),
+        Err(_) => Ok(Err((400, "Request body is not UTF-8"))),
+    }
+}
+
+// None when the line runs past the header size limit. An empty line is the end of the
+// headers, and so is the client closing the connection.
+fn read_header_line(reader: &mut RequestReader) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+pub fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Service Unavailable",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_port() -> u16 {
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port()
+    }
+
+    fn send(port: u16, request: &str) -> std::thread::JoinHandle<String> {
+        let request = request.to_owned();
+        std::thread::spawn(move || {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    fn answer(server: &Server, status: u16, body: &str) -> (String, String, String) {
+        loop {
+            if let Some(request) = server.try_recv() {
+                let seen = (request.method.clone(), request.path.clone(), request.body.clone());
+                request.respond(status, body.to_owned());
+                return seen;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn serves_authorized_requests_and_stops() {
+        let port = free_port();
+        let server = Server::start(port, "secret".to_owned(), || {}).unwrap();
+
+        let response = send(port, "GET /notes HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n").join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 401"));
+
+        let body = r#"{"title":"From a script","body":"hi"}"#;
+        let client = send(
+            port,
+            &format!("POST /notes HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}", body.len(), body),
+        );
+        let (method, path, received) = answer(&server, 201, "{}");
+        assert_eq!((method.as_str(), path.as_str(), received.as_str()), ("POST", "/notes", body));
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 201 Created"));
+        assert!(response.ends_with("\r\n\r\n{}"));
+
+        drop(server);
+        assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_err());
+    }
+
+    #[test]
+    fn refuses_oversized_headers_and_stalled_clients() {
+        let port = free_port();
+        let server = Server::start(port, "secret".to_owned(), || {}).unwrap();
+
+        let request_line = "GET /notes HTTP/1.1\r\nX-Padding: ";
+        let padding = "a".repeat(MAX_HEADER_SIZE as usize - request_line.len());
+        let response = send(port, &format!("{}{}", request_line, padding)).join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 431"));
+
+        let started = Instant::now();
+        let stalled = send(port, "GET /notes HTTP/1.1\r\n");
+        let client = send(port, "GET /notes HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n");
+        answer(&server, 200, "[]");
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 200"));
+        assert!(stalled.join().unwrap().starts_with("HTTP/1.1 408"));
+        assert!(started.elapsed() < REQUEST_TIMEOUT * 2);
+    }
+}
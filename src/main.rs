@@ -5,7 +5,13 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
-use chrono::{DateTime, Local, TimeZone};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, TimeZone};
+
+mod api;
+mod markdown;
+mod pdf;
+mod zip;
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Note {
@@ -13,8 +19,30 @@ struct Note {
     title: String,
     body: String,
     modified: u64,
+    #[serde(skip)]
     editing: bool,
+    #[serde(skip)]
     backup: Option<String>,
+    tags: Vec<String>,
+    created: u64,
+    no_autosave: bool,
+    pinned: bool,
+    pinned_in: Vec<String>,
+    archived: bool,
+    attachments: Vec<String>,
+    icon: Option<String>,
+    last_opened: u64,
+    locked: bool,
+    paper: PaperStyle,
+    expires_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SharedNote {
+    title: String,
+    body: String,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 impl Note {
@@ -26,35 +54,769 @@ impl Note {
             modified: current_unix(),
             editing: false,
             backup: None,
+            tags: Vec::new(),
+            created: current_unix(),
+            no_autosave: false,
+            pinned: false,
+            pinned_in: Vec::new(),
+            archived: false,
+            attachments: Vec::new(),
+            icon: None,
+            last_opened: 0,
+            locked: false,
+            paper: PaperStyle::Plain,
+            expires_at: None,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.archived && self.expires_at.is_some_and(|t| t <= current_unix())
+    }
+
+    // Returns false for locked notes, which have to be unlocked first.
+    fn start_editing(&mut self) -> bool {
+        if self.locked {
+            return false;
+        }
+        if !self.editing {
+            self.backup = Some(self.body.clone());
+            self.editing = true;
+        }
+        true
+    }
+}
+
+const MIN_FONT_SIZE: f32 = 12.0;
+const MAX_FONT_SIZE: f32 = 24.0;
+// Ctrl+scroll zoom is summed as ln(zoom_delta); one wheel notch is about 0.25.
+const ZOOM_SCROLL_STEP: f32 = 0.2;
+const MIN_UI_SCALE: f32 = 0.8;
+const MAX_UI_SCALE: f32 = 2.0;
+const DEFAULT_SIDEBAR_WIDTH: f32 = 200.0;
+const MIN_EDITOR_WIDTH: f32 = 400.0;
+const MAX_EDITOR_WIDTH: f32 = 2000.0;
+const AUTO_SAVE_DELAY: f64 = 1.0;
+const SAVED_INDICATOR_DURATION: f32 = 1.5;
+const SETTINGS_SAVE_DELAY: f64 = 0.5;
+const SAVE_RETRY_DELAY: f64 = 2.0;
+const CAPTURE_POLL_INTERVAL: f64 = 1.0;
+const MAX_HISTORY_VERSIONS: usize = 20;
+const NOTE_LINK_PREFIX: &str = "notes://open?id=";
+const BUNDLE_NOTE: &str = "note.md";
+const BUNDLE_ATTACHMENTS: &str = "attachments/";
+const CAPTURE_REQUEST: &str = ".capture";
+const USAGE: &str = "Usage: notes [--open TITLE | --capture | notes://open?id=ID | FILE]";
+const ZEN_COLUMN_WIDTH: f32 = 700.0;
+const DEFAULT_API_PORT: u16 = 7878;
+const GRID_CARD_SIZE: egui::Vec2 = egui::vec2(200.0, 120.0);
+const NOTE_ICONS: &[&str] = &[
+    "📝", "💡", "🐛", "⭐", "🔥", "✅", "❗", "❓", "📅", "📚", "💼", "🏠", "🎯", "🔒", "💬", "🛒",
+];
+const DEFAULT_WINDOW_SIZE: egui::Vec2 = egui::vec2(800.0, 600.0);
+const MIN_VISIBLE_WINDOW: egui::Vec2 = egui::vec2(100.0, 40.0);
+const QUICK_ACCESS_NOTES: usize = 10;
+
+const SHOW_SHORTCUTS: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F1);
+const SHOW_SHORTCUTS_ALT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Questionmark);
+const ZOOM_IN: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Equals);
+const ZOOM_IN_ALT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Plus);
+const ZOOM_OUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Minus);
+const ZEN_MODE: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F11);
+const FIND_REPLACE: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT), egui::Key::H);
+const DAILY_NOTE: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::D);
+const TOGGLE_WORD_COUNT: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT), egui::Key::W);
+const OPEN_SETTINGS: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Comma);
+const NEXT_REGION: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F6);
+const PREVIOUS_REGION: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::SHIFT, egui::Key::F6);
+const SAVE_AND_CLOSE: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Enter);
+const SAVE_AND_NEW: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT), egui::Key::Enter);
+const DELETE_NOTE: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Delete);
+const INDENT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Tab);
+const OUTDENT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::SHIFT, egui::Key::Tab);
+
+enum ShortcutKeys {
+    Shortcut(egui::KeyboardShortcut),
+    Text(&'static str),
+}
+
+struct ShortcutInfo {
+    category: &'static str,
+    keys: &'static [ShortcutKeys],
+    description: &'static str,
+}
+
+const SHORTCUTS: &[ShortcutInfo] = &[
+    ShortcutInfo {
+        category: "General",
+        keys: &[ShortcutKeys::Shortcut(SHOW_SHORTCUTS), ShortcutKeys::Shortcut(SHOW_SHORTCUTS_ALT)],
+        description: "Show keyboard shortcuts",
+    },
+    ShortcutInfo {
+        category: "General",
+        keys: &[ShortcutKeys::Shortcut(FIND_REPLACE)],
+        description: "Find and replace in all notes",
+    },
+    ShortcutInfo {
+        category: "General",
+        keys: &[ShortcutKeys::Shortcut(DAILY_NOTE)],
+        description: "Open today's daily note",
+    },
+    ShortcutInfo {
+        category: "General",
+        keys: &[ShortcutKeys::Shortcut(OPEN_SETTINGS)],
+        description: "Open settings (Tab, arrows and Space operate them)",
+    },
+    ShortcutInfo {
+        category: "General",
+        keys: &[ShortcutKeys::Text("Escape")],
+        description: "Close dialog, else cancel drag, stop editing, leave zen mode or clear search",
+    },
+    ShortcutInfo {
+        category: "View",
+        keys: &[ShortcutKeys::Shortcut(ZOOM_IN), ShortcutKeys::Shortcut(ZOOM_IN_ALT)],
+        description: "Increase font size",
+    },
+    ShortcutInfo {
+        category: "View",
+        keys: &[ShortcutKeys::Shortcut(ZOOM_OUT)],
+        description: "Decrease font size",
+    },
+    ShortcutInfo {
+        category: "View",
+        keys: &[ShortcutKeys::Text("Ctrl+Scroll")],
+        description: "Change font size",
+    },
+    ShortcutInfo {
+        category: "View",
+        keys: &[ShortcutKeys::Shortcut(ZEN_MODE)],
+        description: "Toggle zen mode",
+    },
+    ShortcutInfo {
+        category: "View",
+        keys: &[ShortcutKeys::Shortcut(TOGGLE_WORD_COUNT)],
+        description: "Show or hide the word count",
+    },
+    ShortcutInfo {
+        category: "Editor",
+        keys: &[ShortcutKeys::Shortcut(SAVE_AND_CLOSE)],
+        description: "Save and return to the notes list",
+    },
+    ShortcutInfo {
+        category: "Editor",
+        keys: &[ShortcutKeys::Shortcut(SAVE_AND_NEW)],
+        description: "Save and start a new note",
+    },
+    ShortcutInfo {
+        category: "Editor",
+        keys: &[ShortcutKeys::Shortcut(INDENT)],
+        description: "Indent line or selection",
+    },
+    ShortcutInfo {
+        category: "Editor",
+        keys: &[ShortcutKeys::Shortcut(OUTDENT)],
+        description: "Outdent line or selection",
+    },
+    ShortcutInfo {
+        category: "Notes list",
+        keys: &[ShortcutKeys::Shortcut(NEXT_REGION), ShortcutKeys::Shortcut(PREVIOUS_REGION)],
+        description: "Move focus between search, the notes list and the editor",
+    },
+    ShortcutInfo {
+        category: "Notes list",
+        keys: &[ShortcutKeys::Shortcut(DELETE_NOTE)],
+        description: "Delete the selected note (or checked notes)",
+    },
+    ShortcutInfo {
+        category: "Notes list",
+        keys: &[ShortcutKeys::Text(":")],
+        description: "Open the command bar (goto, new, delete, tag, untag, pin, archive)",
+    },
+];
+
+const NOTES_SCHEMA_VERSION: u32 = 12;
+
+#[derive(Serialize, Deserialize, Default)]
+struct NotesIndex {
+    version: u32,
+    order: Vec<u128>,
+}
+
+struct NoteStore {
+    dir: std::path::PathBuf,
+    written: std::collections::HashMap<u128, String>,
+    order: Vec<u128>,
+    compact: bool,
+    // Note files that couldn't be read or parsed, kept in the index so they aren't forgotten.
+    skipped: Vec<(u128, String)>,
+}
+
+struct InstanceLock {
+    path: std::path::PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum ThemeMode {
+    Dark,
+    Light,
+    System,
+    HighContrast,
+}
+
+impl ThemeMode {
+    const ALL: [ThemeMode; 4] = [ThemeMode::Dark, ThemeMode::Light, ThemeMode::System, ThemeMode::HighContrast];
+
+    fn label(self) -> &'static str {
+        match self {
+            ThemeMode::Dark => "Dark",
+            ThemeMode::Light => "Light",
+            ThemeMode::System => "System",
+            ThemeMode::HighContrast => "High contrast",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum SidebarSide {
+    Left,
+    Right,
+}
+
+impl SidebarSide {
+    const ALL: [SidebarSide; 2] = [SidebarSide::Left, SidebarSide::Right];
+
+    fn label(self) -> &'static str {
+        match self {
+            SidebarSide::Left => "Left",
+            SidebarSide::Right => "Right",
+        }
+    }
+
+    fn panel_side(self) -> egui::panel::Side {
+        match self {
+            SidebarSide::Left => egui::panel::Side::Left,
+            SidebarSide::Right => egui::panel::Side::Right,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum WordCountMode {
+    Whitespace,
+    Cjk,
+}
+
+impl WordCountMode {
+    const ALL: [WordCountMode; 2] = [WordCountMode::Whitespace, WordCountMode::Cjk];
+
+    fn label(self) -> &'static str {
+        match self {
+            WordCountMode::Whitespace => "Words separated by spaces",
+            WordCountMode::Cjk => "Count CJK characters individually",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum NotesLayout {
+    List,
+    Grid,
+}
+
+impl NotesLayout {
+    fn label(self) -> &'static str {
+        match self {
+            NotesLayout::List => "List",
+            NotesLayout::Grid => "Grid",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum PaperStyle {
+    Plain,
+    Lined,
+    Tinted,
+}
+
+impl PaperStyle {
+    const ALL: [PaperStyle; 3] = [PaperStyle::Plain, PaperStyle::Lined, PaperStyle::Tinted];
+
+    fn label(self) -> &'static str {
+        match self {
+            PaperStyle::Plain => "Plain",
+            PaperStyle::Lined => "Lined",
+            PaperStyle::Tinted => "Tinted",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PomodoroPhase {
+    Work,
+    Break,
+}
+
+struct SaveFailure {
+    error: String,
+    attempts: u32,
+    retry_at: std::time::Instant,
+}
+
+struct Pomodoro {
+    phase: PomodoroPhase,
+    ends: std::time::Instant,
+    words_at_start: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FocusRegion {
+    Search,
+    List,
+    Editor,
+}
+
+impl FocusRegion {
+    const ALL: [FocusRegion; 3] = [FocusRegion::Search, FocusRegion::List, FocusRegion::Editor];
+
+    fn step(self, forward: bool) -> FocusRegion {
+        let pos = Self::ALL.iter().position(|&r| r == self).unwrap_or(0);
+        let len = Self::ALL.len();
+        Self::ALL[if forward { (pos + 1) % len } else { (pos + len - 1) % len }]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum NewNotePosition {
+    Top,
+    Bottom,
+}
+
+impl NewNotePosition {
+    const ALL: [NewNotePosition; 2] = [NewNotePosition::Top, NewNotePosition::Bottom];
+
+    fn label(self) -> &'static str {
+        match self {
+            NewNotePosition::Top => "Top",
+            NewNotePosition::Bottom => "Bottom",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum TagSort {
+    Name,
+    Frequency,
+}
+
+impl TagSort {
+    const ALL: [TagSort; 2] = [TagSort::Name, TagSort::Frequency];
+
+    fn label(self) -> &'static str {
+        match self {
+            TagSort::Name => "Name",
+            TagSort::Frequency => "Most used",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum SelectionStyle {
+    Background,
+    LeftBar,
+    BarAndBackground,
+}
+
+impl SelectionStyle {
+    const ALL: [SelectionStyle; 3] = [SelectionStyle::Background, SelectionStyle::LeftBar, SelectionStyle::BarAndBackground];
+
+    fn label(self) -> &'static str {
+        match self {
+            SelectionStyle::Background => "Background",
+            SelectionStyle::LeftBar => "Bar",
+            SelectionStyle::BarAndBackground => "Both",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum SortMode {
+    Manual,
+    Title,
+    Modified,
+    Opened,
+}
+
+impl SortMode {
+    const ALL: [SortMode; 4] = [SortMode::Manual, SortMode::Title, SortMode::Modified, SortMode::Opened];
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Manual => "Manual",
+            SortMode::Title => "Title",
+            SortMode::Modified => "Modified",
+            SortMode::Opened => "Last opened",
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 struct AppSettings {
-    dark_mode: bool,
+    theme: ThemeMode,
     font_size: f32,
+    ui_scale: f32,
+    max_editor_width: f32,
+    sidebar_side: SidebarSide,
+    notes_layout: NotesLayout,
+    sidebar_title_max: usize,
+    selection_style: SelectionStyle,
+    startup_view: StartupView,
+    last_view: AppView,
     auto_save: bool,
     show_word_count: bool,
+    word_count_mode: WordCountMode,
     drag_and_drop: bool,
+    float_selected: bool,
+    sort_mode: SortMode,
+    sort_ascending: bool,
+    trim_trailing_whitespace: bool,
+    indent_width: usize,
+    indent_with_tabs: bool,
+    continue_lists: bool,
+    show_previews: bool,
+    click_selected_to_edit: bool,
+    confirm_switch_while_editing: bool,
+    highlight_unsaved: bool,
+    save_on_switch: bool,
+    remember_search: bool,
+    compact_storage: bool,
+    new_note_position: NewNotePosition,
+    api_enabled: bool,
+    api_port: u16,
+    api_token: String,
+    quick_capture_enabled: bool,
+    quick_capture_shortcut: String,
+    prune_untitled: bool,
+    prune_untitled_confirmed: bool,
+    paper_styles: bool,
+    pomodoro_enabled: bool,
+    pomodoro_work_minutes: u32,
+    pomodoro_break_minutes: u32,
+    pomodoro_notify: bool,
+    save_retry_max_seconds: u32,
+    auto_capitalize: bool,
+    export_front_matter: bool,
+    export_name_template: String,
+    scratchpad: String,
+    templates: Vec<NoteTemplate>,
+    default_template: String,
+    daily_note_title: String,
+    body_hint: String,
+    daily_note_template: String,
+    saved_searches: Vec<SavedSearch>,
+    layout_presets: Vec<LayoutPreset>,
+    note_soft_limit: usize,
+    tag_sort: TagSort,
 }
 
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
-            dark_mode: true,
+            theme: ThemeMode::Dark,
             font_size: 17.0,
+            ui_scale: 1.0,
+            max_editor_width: 800.0,
+            sidebar_side: SidebarSide::Left,
+            notes_layout: NotesLayout::List,
+            sidebar_title_max: 40,
+            selection_style: SelectionStyle::Background,
+            startup_view: StartupView::Notes,
+            last_view: AppView::Notes,
             auto_save: true,
             show_word_count: false,
+            word_count_mode: WordCountMode::Whitespace,
             drag_and_drop: false,
+            float_selected: false,
+            sort_mode: SortMode::Manual,
+            sort_ascending: true,
+            trim_trailing_whitespace: false,
+            indent_width: 4,
+            indent_with_tabs: false,
+            continue_lists: false,
+            show_previews: false,
+            click_selected_to_edit: false,
+            confirm_switch_while_editing: true,
+            highlight_unsaved: true,
+            save_on_switch: true,
+            remember_search: false,
+            compact_storage: false,
+            new_note_position: NewNotePosition::Top,
+            api_enabled: false,
+            api_port: DEFAULT_API_PORT,
+            api_token: String::new(),
+            quick_capture_enabled: true,
+            quick_capture_shortcut: "Ctrl+Alt+N".to_owned(),
+            prune_untitled: false,
+            prune_untitled_confirmed: false,
+            paper_styles: false,
+            pomodoro_enabled: false,
+            pomodoro_work_minutes: 25,
+            pomodoro_break_minutes: 5,
+            pomodoro_notify: true,
+            save_retry_max_seconds: 60,
+            auto_capitalize: false,
+            export_front_matter: true,
+            export_name_template: "{title}".to_owned(),
+            scratchpad: String::new(),
+            templates: Vec::new(),
+            default_template: String::new(),
+            daily_note_title: "%Y-%m-%d".to_owned(),
+            body_hint: "Start writing…".to_owned(),
+            daily_note_template: String::new(),
+            saved_searches: Vec::new(),
+            layout_presets: vec![
+                LayoutPreset {
+                    name: "Writing".to_owned(),
+                    sidebar_width: 160.0,
+                    notes_layout: NotesLayout::List,
+                    show_previews: false,
+                    font_size: 20.0,
+                    max_editor_width: 1000.0,
+                },
+                LayoutPreset {
+                    name: "Browsing".to_owned(),
+                    sidebar_width: 320.0,
+                    notes_layout: NotesLayout::List,
+                    show_previews: true,
+                    font_size: 15.0,
+                    max_editor_width: 700.0,
+                },
+            ],
+            note_soft_limit: 0,
+            tag_sort: TagSort::Name,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct NoteTemplate {
+    name: String,
+    body: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedSearch {
+    name: String,
+    query: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LayoutPreset {
+    name: String,
+    sidebar_width: f32,
+    notes_layout: NotesLayout,
+    show_previews: bool,
+    font_size: f32,
+    max_editor_width: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct NoteVersion {
+    saved: u64,
+    body: String,
+}
+
+type NoteHistory = std::collections::HashMap<u128, Vec<NoteVersion>>;
+// Notes whose saved body changed, with the version that was on disk before.
+type BodyChanges = Vec<(u128, Option<NoteVersion>)>;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct WindowGeometry {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct SessionState {
+    window: Option<WindowGeometry>,
+    #[serde(default)]
+    search: Option<SavedSearchState>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedSearchState {
+    query: String,
+    scope: SearchScope,
+    filter: Option<FilterPreset>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum EscapeAction {
+    CancelDrag,
+    CancelEdit,
+    LeaveZenMode,
+    ClearSearch,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Markdown,
+    Html,
+    Pdf,
+    Bundle,
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 4] = [ExportFormat::Markdown, ExportFormat::Html, ExportFormat::Pdf, ExportFormat::Bundle];
+
+    fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Pdf => "PDF",
+            ExportFormat::Bundle => "Bundle (.zip)",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Bundle => "zip",
+        }
+    }
+}
+
+struct ExportDialog {
+    note_id: u128,
+    format: ExportFormat,
+    path: String,
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum NoteAction {
+    Edit,
+    Duplicate,
+    TogglePin,
+    ToggleTagPin,
+    ToggleLock,
+    ToggleArchive,
+    Delete,
+}
+
+#[derive(Clone, Copy)]
+enum MarkdownFormat {
+    Bold,
+    Italic,
+    Heading,
+    List,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum SearchScope {
+    Title,
+    Body,
+    Both,
+}
+
+impl SearchScope {
+    const ALL: [SearchScope; 3] = [SearchScope::Title, SearchScope::Body, SearchScope::Both];
+
+    fn label(self) -> &'static str {
+        match self {
+            SearchScope::Title => "Title",
+            SearchScope::Body => "Body",
+            SearchScope::Both => "Both",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum FilterPreset {
+    EditedToday,
+    ThisWeek,
+    Archived,
+    Orphaned,
+}
+
+impl FilterPreset {
+    const ALL: [FilterPreset; 4] =
+        [FilterPreset::EditedToday, FilterPreset::ThisWeek, FilterPreset::Archived, FilterPreset::Orphaned];
+
+    fn label(self) -> &'static str {
+        match self {
+            FilterPreset::EditedToday => "Edited today",
+            FilterPreset::ThisWeek => "This week",
+            FilterPreset::Archived => "Archived",
+            FilterPreset::Orphaned => "Orphaned",
         }
     }
+
+    fn since(self) -> u64 {
+        let today = Local::now().date_naive();
+        let start = match self {
+            FilterPreset::EditedToday => today,
+            FilterPreset::ThisWeek => today - chrono::Days::new(today.weekday().num_days_from_monday() as u64),
+            FilterPreset::Archived | FilterPreset::Orphaned => return 0,
+        };
+        start
+            .and_hms_opt(0, 0, 0)
+            .and_then(|dt| dt.and_local_timezone(Local).earliest())
+            .map_or(0, |dt| dt.timestamp().max(0) as u64)
+    }
+}
+
+enum LaunchAction {
+    Open(String),
+    OpenId(u128),
+    Import(std::path::PathBuf),
+    Capture,
 }
 
-#[derive(PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
 enum AppView {
+    #[default]
+    Notes,
+    Scratchpad,
+    Tags,
+    Settings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum StartupView {
     Notes,
+    Scratchpad,
     Settings,
+    LastUsed,
+}
+
+impl StartupView {
+    const ALL: [StartupView; 4] = [StartupView::Notes, StartupView::Scratchpad, StartupView::Settings, StartupView::LastUsed];
+
+    fn label(self) -> &'static str {
+        match self {
+            StartupView::Notes => "Notes",
+            StartupView::Scratchpad => "Scratchpad",
+            StartupView::Settings => "Settings",
+            StartupView::LastUsed => "Last used",
+        }
+    }
+
+    fn view(self, last_view: AppView) -> AppView {
+        match self {
+            StartupView::Notes => AppView::Notes,
+            StartupView::Scratchpad => AppView::Scratchpad,
+            StartupView::Settings => AppView::Settings,
+            StartupView::LastUsed => last_view,
+        }
+    }
 }
 
 fn current_unix() -> u64 {
@@ -64,27 +826,119 @@ fn current_unix() -> u64 {
         .unwrap_or(0)
 }
 
-fn get_data_path() -> String {
-    let mut path = dirs::data_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
+fn relative_time(secs_ago: u64) -> String {
+    let (amount, unit) = match secs_ago {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (secs_ago / 60, "minute"),
+        3600..86400 => (secs_ago / 3600, "hour"),
+        86400..2592000 => (secs_ago / 86400, "day"),
+        2592000..31536000 => (secs_ago / 2592000, "month"),
+        _ => (secs_ago / 31536000, "year"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+fn get_location_file() -> Option<std::path::PathBuf> {
+    let mut path = dirs::config_dir()?;
     path.push("notes");
+    path.push("data_location");
+    Some(path)
+}
+
+fn get_app_dir() -> std::path::PathBuf {
+    let custom = get_location_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|dir| dir.trim().to_owned())
+        .filter(|dir| !dir.is_empty());
+    match custom {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => {
+            let mut path = dirs::data_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
+            path.push("notes");
+            path
+        }
+    }
+}
+
+fn save_app_dir(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let path = get_location_file().ok_or("no configuration directory on this system")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, dir.to_string_lossy().as_bytes())?;
+    Ok(())
+}
+
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".write_test");
+    fs::write(&probe, b"ok")?;
+    fs::remove_file(probe)
+}
+
+fn get_data_path(app_dir: &Path) -> String {
+    let mut path = app_dir.to_path_buf();
     let _ = std::fs::create_dir_all(&path);
     path.push("notes.json");
     path.to_string_lossy().to_string()
 }
 
-fn get_settings_path() -> String {
-    let mut path = dirs::data_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
+fn get_notes_dir(app_dir: &Path) -> std::path::PathBuf {
+    let mut path = app_dir.to_path_buf();
     path.push("notes");
     let _ = std::fs::create_dir_all(&path);
+    path
+}
+
+fn get_attachments_dir(app_dir: &Path) -> std::path::PathBuf {
+    let mut path = app_dir.to_path_buf();
+    path.push("attachments");
+    path
+}
+
+fn get_settings_path(app_dir: &Path) -> String {
+    let mut path = app_dir.to_path_buf();
+    let _ = std::fs::create_dir_all(&path);
     path.push("settings.json");
     path.to_string_lossy().to_string()
 }
 
+fn get_history_path(app_dir: &Path) -> String {
+    let mut path = app_dir.to_path_buf();
+    let _ = std::fs::create_dir_all(&path);
+    path.push("history.json");
+    path.to_string_lossy().to_string()
+}
+
+fn get_session_path(app_dir: &Path) -> String {
+    let mut path = app_dir.to_path_buf();
+    let _ = std::fs::create_dir_all(&path);
+    path.push("session.json");
+    path.to_string_lossy().to_string()
+}
+
+struct DataDirProblem {
+    error: String,
+    alternative: String,
+}
+
+#[derive(Default)]
+struct FindReplace {
+    find: String,
+    replace: String,
+    case_sensitive: bool,
+    excluded: std::collections::HashSet<u128>,
+    confirming: bool,
+}
+
 struct NotesApp {
     notes: Vec<Note>,
     selected: Option<usize>,
     search: String,
-    data_path: String,
+    search_scope: SearchScope,
+    saved_search_name: Option<String>,
+    filter_preset: Option<FilterPreset>,
+    store: NoteStore,
     settings_path: String,
     settings: AppSettings,
     dirty: bool,
@@ -92,647 +946,6661 @@ struct NotesApp {
     drag_start_pos: Option<egui::Pos2>,
     current_view: AppView,
     settings_changed: bool,
+    session_path: String,
+    session: SessionState,
+    window_checked: bool,
+    pending_sidebar_width: Option<f32>,
+    zoom_scroll: f32,
+    sidebar_width: f32,
+    layout_preset_name: String,
+    show_shortcuts: bool,
+    multi_select: bool,
+    checked: std::collections::HashSet<u128>,
+    batch_tag_input: String,
+    window_focused: bool,
+    dirty_since: Option<f64>,
+    settings_changed_since: Option<f64>,
+    was_in_settings: bool,
+    last_saved: Option<std::time::Instant>,
+    save_failure: Option<SaveFailure>,
+    load_problems: Vec<String>,
+    history_path: String,
+    history: NoteHistory,
+    history_pending: std::collections::HashSet<u128>,
+    history_note: Option<u128>,
+    history_preview: Option<usize>,
+    paste_json: Option<String>,
+    paste_json_error: Option<String>,
+    zen_mode: bool,
+    find_replace: Option<FindReplace>,
+    export_dialog: Option<ExportDialog>,
+    pending_switch: Option<u128>,
+    pending_capture: bool,
+    storage_status: Option<String>,
+    report_path: String,
+    app_dir: std::path::PathBuf,
+    attachments_dir: std::path::PathBuf,
+    attach_input: String,
+    attach_error: Option<String>,
+    orphaned_attachments: Vec<String>,
+    tag_rename: Option<(String, String)>,
+    tag_delete: Option<String>,
+    instance_lock: Option<InstanceLock>,
+    // Set while the lock prompt is showing; the inner value is the other instance's
+    // process id, when the lock file names one.
+    lock_holder: Option<Option<u32>>,
+    read_only: bool,
+    command_input: Option<String>,
+    command_error: Option<String>,
+    import_folder_path: String,
+    import_bundle_path: String,
+    data_dir_problem: Option<DataDirProblem>,
+    focus_first_setting: bool,
+    settings_focus: Option<egui::Id>,
+    last_opened_dirty: bool,
+    api_server: Option<api::Server>,
+    api_config: Option<(u16, String)>,
+    api_error: Option<String>,
+    capture_checked_at: f64,
+    prune_prompt: Option<usize>,
+    prune_skipped: bool,
+    discard_all_prompt: bool,
+    discard_all_error: Option<String>,
+    preview_selection: Option<u128>,
+    session_notes_created: usize,
+    session_words_written: usize,
+    session_word_baseline: Option<(u128, usize)>,
+    focus_list: bool,
+    locked_delete: Option<u128>,
+    pomodoro: Option<Pomodoro>,
+    pomodoro_result: Option<String>,
+    selected_position: Option<(u128, usize)>,
+    scroll_to_selected: bool,
 }
 
 impl Default for NotesApp {
     fn default() -> Self {
-        let data_path = get_data_path();
-        let settings_path = get_settings_path();
-        let notes = load_notes(&data_path).unwrap_or_default();
+        Self::open(get_app_dir())
+    }
+}
+
+impl NotesApp {
+    fn open(app_dir: std::path::PathBuf) -> Self {
+        let data_dir_problem = check_writable(&app_dir).err().map(|e| DataDirProblem {
+            error: format!("{} can't be written to: {}", app_dir.display(), e),
+            alternative: dirs::document_dir()
+                .or_else(dirs::home_dir)
+                .map(|dir| dir.join("Notes").to_string_lossy().to_string())
+                .unwrap_or_default(),
+        });
+        let mut store = NoteStore::new(get_notes_dir(&app_dir));
+        let (instance_lock, lock_holder) = match InstanceLock::acquire(store.dir.join(".lock")) {
+            Ok(lock) => {
+                let _ = fs::remove_file(store.dir.join(CAPTURE_REQUEST));
+                (Some(lock), None)
+            }
+            Err(pid) => (None, Some(pid)),
+        };
+        let settings_path = get_settings_path(&app_dir);
+        let notes = store.load_or_migrate(get_data_path(&app_dir)).unwrap_or_else(|e| {
+            eprintln!("Failed to load notes: {}", e);
+            Vec::new()
+        });
+        let load_problems = store.skipped_files();
+        for problem in &load_problems {
+            eprintln!("Skipped {}", problem);
+        }
         let settings = load_settings(&settings_path).unwrap_or_default();
+        store.compact = settings.compact_storage;
+        let session_path = get_session_path(&app_dir);
+        let session = load_session(&session_path).unwrap_or_default();
+        let history_path = get_history_path(&app_dir);
+        let history = load_history(&history_path).unwrap_or_default();
         let selected = if notes.is_empty() { None } else { Some(0) };
+        let current_view = settings.startup_view.view(settings.last_view);
+        let last_search = session.search.clone().filter(|_| settings.remember_search);
         Self {
             notes,
             selected,
-            search: String::new(),
-            data_path,
+            search: last_search.as_ref().map(|s| s.query.clone()).unwrap_or_default(),
+            search_scope: last_search.as_ref().map_or(SearchScope::Both, |s| s.scope),
+            saved_search_name: None,
+            filter_preset: last_search.and_then(|s| s.filter),
+            store,
             settings_path,
             settings,
             dirty: false,
             dragging: None,
             drag_start_pos: None,
-            current_view: AppView::Notes,
+            current_view,
             settings_changed: false,
+            session_path,
+            session,
+            window_checked: false,
+            pending_sidebar_width: None,
+            zoom_scroll: 0.0,
+            sidebar_width: DEFAULT_SIDEBAR_WIDTH,
+            layout_preset_name: String::new(),
+            show_shortcuts: false,
+            multi_select: false,
+            checked: std::collections::HashSet::new(),
+            batch_tag_input: String::new(),
+            window_focused: true,
+            dirty_since: None,
+            settings_changed_since: None,
+            was_in_settings: false,
+            last_saved: None,
+            save_failure: None,
+            load_problems,
+            history_path,
+            history,
+            history_pending: std::collections::HashSet::new(),
+            history_note: None,
+            history_preview: None,
+            paste_json: None,
+            paste_json_error: None,
+            zen_mode: false,
+            find_replace: None,
+            export_dialog: None,
+            pending_switch: None,
+            pending_capture: false,
+            storage_status: None,
+            report_path: default_report_path(),
+            attachments_dir: get_attachments_dir(&app_dir),
+            app_dir,
+            attach_input: String::new(),
+            attach_error: None,
+            orphaned_attachments: Vec::new(),
+            tag_rename: None,
+            tag_delete: None,
+            instance_lock,
+            lock_holder,
+            read_only: lock_holder.is_some(),
+            command_input: None,
+            command_error: None,
+            import_folder_path: String::new(),
+            import_bundle_path: String::new(),
+            data_dir_problem,
+            focus_first_setting: false,
+            settings_focus: None,
+            last_opened_dirty: false,
+            api_server: None,
+            api_config: None,
+            api_error: None,
+            capture_checked_at: 0.0,
+            prune_prompt: None,
+            prune_skipped: false,
+            discard_all_prompt: false,
+            discard_all_error: None,
+            preview_selection: None,
+            session_notes_created: 0,
+            session_words_written: 0,
+            session_word_baseline: None,
+            focus_list: false,
+            locked_delete: None,
+            pomodoro: None,
+            pomodoro_result: None,
+            selected_position: None,
+            scroll_to_selected: false,
         }
     }
-}
 
-impl NotesApp {
     fn add_note(&mut self) {
         let id = rand::random::<u128>();
         let mut note = Note::new(id);
         note.title = format!("Note {}", self.notes.len() + 1);
-        self.notes.insert(0, note);
-        self.selected = Some(0);
+        if let Some(body) = self.template_body(&self.settings.default_template) {
+            note.body = body;
+        }
+        let idx = match self.settings.new_note_position {
+            NewNotePosition::Top => 0,
+            NewNotePosition::Bottom => self.notes.len(),
+        };
+        self.notes.insert(idx, note);
+        self.selected = Some(idx);
+        self.session_notes_created += 1;
         self.dirty = true;
     }
 
-    fn delete_selected(&mut self) {
-        if let Some(idx) = self.selected {
-            if idx < self.notes.len() {
-                self.notes.remove(idx);
-                self.selected = if self.notes.is_empty() { None } else { Some(0.min(idx)) };
-                self.dirty = true;
-            }
-        }
+    fn show_window(&self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
     }
 
-    fn save_notes(&mut self) {
-        if let Err(e) = save_notes(&self.data_path, &self.notes) {
-            eprintln!("Failed to save notes: {}", e);
-        } else {
-            self.dirty = false;
+    fn quick_capture(&mut self, ctx: &egui::Context) {
+        self.show_window(ctx);
+        self.zen_mode = false;
+        self.current_view = AppView::Notes;
+        // The capture note doesn't exist yet, so it's switched to as the index past the end.
+        if self.prepare_switch(self.notes.len()) {
+            self.add_note();
+            self.focus_region(ctx, FocusRegion::Editor);
         }
     }
 
-    fn save_settings(&mut self) {
-        if let Err(e) = save_settings(&self.settings_path, &self.settings) {
-            eprintln!("Failed to save settings: {}", e);
-        } else {
-            self.settings_changed = false;
+    fn open_note(&mut self, ctx: &egui::Context, id: u128) {
+        let Some(idx) = self.notes.iter().position(|n| n.id == id) else {
+            return;
+        };
+        self.show_window(ctx);
+        self.zen_mode = false;
+        self.current_view = AppView::Notes;
+        if self.prepare_switch(idx) {
+            self.pick_note(idx);
+            self.scroll_to_selected = true;
         }
     }
 
-    fn apply_theme(&self, ctx: &egui::Context) {
-        if self.settings.dark_mode {
-            let visuals_dark = egui::Visuals::dark();
-            ctx.set_visuals(visuals_dark);
-        } else {
-            let visuals_light = egui::Visuals::light();
-            ctx.set_visuals(visuals_light);
+    // Pinned notes, one click away from any view.
+    fn show_quick_access_menu(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut open = None;
+        let mut new_note = false;
+        ui.menu_button("📌", |ui| {
+            let pinned: Vec<&Note> = self.notes.iter().filter(|n| n.pinned && !n.archived).take(QUICK_ACCESS_NOTES).collect();
+            if pinned.is_empty() {
+                ui.label(egui::RichText::new("Pin a note to list it here").size(10.0).weak());
+            }
+            for note in pinned {
+                let title = match &note.icon {
+                    Some(icon) => format!("{} {}", icon, truncate_title(&note.title, 40)),
+                    None => truncate_title(&note.title, 40),
+                };
+                if ui.button(title).clicked() {
+                    open = Some(note.id);
+                    ui.close();
+                }
+            }
+            ui.separator();
+            if ui.button("New note").clicked() {
+                new_note = true;
+                ui.close();
+            }
+        })
+        .response
+        .on_hover_text("Pinned notes");
+
+        if let Some(id) = open {
+            self.open_note(ctx, id);
+        } else if new_note {
+            self.quick_capture(ctx);
         }
     }
 
-    fn apply_font_settings(&self, ctx: &egui::Context) {
-        let mut style = (*ctx.style()).clone();
-
-        style.text_styles.get_mut(&egui::TextStyle::Body).unwrap().size = self.settings.font_size;
-        style.text_styles.get_mut(&egui::TextStyle::Heading).unwrap().size = self.settings.font_size + 7.0;
-        style.text_styles.get_mut(&egui::TextStyle::Button).unwrap().size = self.settings.font_size - 2.0;
+    fn quick_capture_shortcut(&self) -> Option<egui::KeyboardShortcut> {
+        if !self.settings.quick_capture_enabled {
+            return None;
+        }
+        parse_shortcut(&self.settings.quick_capture_shortcut).ok()
+    }
 
-        ctx.set_style(style);
+    // `notes --capture` started while this instance holds the lock leaves a request
+    // file in the notes folder instead of opening a second window.
+    fn poll_capture_requests(&mut self, ctx: &egui::Context) {
+        if !self.settings.quick_capture_enabled || self.read_only {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        // A request waits while a dialog is open, so it can't change the note the dialog is about.
+        if now - self.capture_checked_at >= CAPTURE_POLL_INTERVAL && !self.dialog_open() {
+            self.capture_checked_at = now;
+            if fs::remove_file(self.store.dir.join(CAPTURE_REQUEST)).is_ok() {
+                self.quick_capture(ctx);
+            }
+        }
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64(CAPTURE_POLL_INTERVAL));
     }
 
-    fn move_note(&mut self, from: usize, to: usize) {
-        let len = self.notes.len();
-        if from >= len || to > len || from == to {
+    fn sync_api_server(&mut self, ctx: &egui::Context) {
+        let wanted = (self.settings.api_enabled && !self.read_only && !self.settings.api_token.is_empty())
+            .then(|| (self.settings.api_port, self.settings.api_token.clone()));
+        if wanted == self.api_config {
             return;
         }
+        self.api_server = None;
+        self.api_error = None;
+        if let Some((port, token)) = wanted.clone() {
+            let ctx = ctx.clone();
+            match api::Server::start(port, token, move || ctx.request_repaint()) {
+                Ok(server) => self.api_server = Some(server),
+                Err(e) => {
+                    eprintln!("Failed to start API server: {}", e);
+                    self.api_error = Some(format!("Couldn't listen on port {}: {}", port, e));
+                }
+            }
+        }
+        self.api_config = wanted;
+    }
 
-        let selected_id = self.selected.and_then(|s| self.notes.get(s).map(|n| n.id));
+    fn handle_api_requests(&mut self) {
+        while let Some(request) = self.api_server.as_ref().and_then(api::Server::try_recv) {
+            let (status, body) = self.api_response(&request.method, &request.path, &request.body);
+            request.respond(status, body);
+        }
+    }
 
-        let note = self.notes.remove(from);
+    fn api_response(&mut self, method: &str, path: &str, body: &str) -> (u16, String) {
+        let note_json = |note: &Note| {
+            serde_json::json!({
+                "id": format!("{:032x}", note.id),
+                "title": note.title,
+                "body": note.body,
+                "tags": note.tags,
+                "created": note.created,
+                "modified": note.modified,
+                "pinned": note.pinned,
+                "archived": note.archived,
+                "expires_at": note.expires_at,
+            })
+        };
+        let path = path.split('?').next().unwrap_or_default().trim_end_matches('/');
 
-        let insert_at = if to > from { to - 1 } else { to };
-        let insert_at = insert_at.min(self.notes.len());
+        match (method, path) {
+            ("GET", "/notes") => (200, serde_json::Value::Array(self.notes.iter().map(note_json).collect()).to_string()),
+            ("POST", "/notes") => {
+                let shared: SharedNote = match serde_json::from_str(body) {
+                    Ok(shared) => shared,
+                    Err(e) => return (400, api::error_body(&format!("Invalid note: {}", e))),
+                };
+                let mut note = Note::new(rand::random::<u128>());
+                if !shared.title.trim().is_empty() {
+                    note.title = shared.title;
+                }
+                note.body = normalize_line_endings(&shared.body);
+                note.tags = shared.tags;
+                let response = note_json(&note).to_string();
+                match self.settings.new_note_position {
+                    NewNotePosition::Top => {
+                        self.notes.insert(0, note);
+                        self.selected = self.selected.map(|i| i + 1);
+                        self.dragging = self.dragging.map(|i| i + 1);
+                    }
+                    NewNotePosition::Bottom => self.notes.push(note),
+                }
+                self.save_notes();
+                (201, response)
+            }
+            (_, "/notes") => (405, api::error_body("Use GET or POST")),
+            (method, path) => match path.strip_prefix("/notes/") {
+                Some(id) => {
+                    let note = u128::from_str_radix(id, 16).ok().and_then(|id| self.notes.iter().find(|n| n.id == id));
+                    match (method, note) {
+                        ("GET", Some(note)) => (200, note_json(note).to_string()),
+                        ("GET", None) => (404, api::error_body("No note with that id")),
+                        _ => (405, api::error_body("Use GET")),
+                    }
+                }
+                None => (404, api::error_body("Not found")),
+            },
+        }
+    }
 
-        self.notes.insert(insert_at, note);
+    fn import_notes_folder(&mut self) {
+        let root = std::path::PathBuf::from(self.import_folder_path.trim());
+        let (notes, skipped) = match import_folder(&root) {
+            Ok(result) => result,
+            Err(e) => {
+                self.storage_status = Some(format!("Failed to import {}: {}", root.display(), e));
+                return;
+            }
+        };
 
-        self.selected = selected_id.and_then(|id| {
-            self.notes.iter().position(|n| n.id == id)
-        });
+        let mut status = format!("Imported {} notes from {}", notes.len(), root.display());
+        if !skipped.is_empty() {
+            let names: Vec<String> = skipped
+                .iter()
+                .take(5)
+                .map(|p| p.strip_prefix(&root).unwrap_or(p).display().to_string())
+                .collect();
+            let more = if skipped.len() > names.len() { ", …" } else { "" };
+            status.push_str(&format!("; skipped {} files ({}{})", skipped.len(), names.join(", "), more));
+        }
+        self.storage_status = Some(status);
 
-        self.dirty = true;
+        if !notes.is_empty() {
+            self.notes.splice(0..0, notes);
+            self.selected = Some(0);
+            self.dirty = true;
+        }
     }
 
-    fn show_settings_page(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.heading("Settings");
-        ui.separator();
-        ui.add_space(10.0);
+    fn import_note_bundle(&mut self, path: &Path) {
+        if self.read_only {
+            self.storage_status = Some("Notes is open read-only, so bundles can't be imported".to_owned());
+            return;
+        }
+        match import_bundle(path, &self.attachments_dir) {
+            Ok(note) => {
+                self.storage_status = Some(format!(
+                    "Imported {} with {} attachments",
+                    note.title,
+                    note.attachments.len()
+                ));
+                self.notes.insert(0, note);
+                self.selected = Some(0);
+                self.dirty = true;
+            }
+            Err(e) => self.storage_status = Some(format!("Failed to import {}: {}", path.display(), e)),
+        }
+    }
 
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            ui.group(|ui| {
-                ui.label(egui::RichText::new("Appearance").size(18.0));
-                ui.add_space(5.0);
+    fn run_command(&mut self, input: &str) -> Result<(), String> {
+        let input = input.trim();
+        let (command, arg) = input.split_once(' ').map_or((input, ""), |(c, a)| (c, a.trim()));
+        let selected = self.selected.filter(|&idx| idx < self.notes.len());
 
-                ui.horizontal(|ui| {
-                    ui.label("Theme:");
-                    if ui.selectable_label(self.settings.dark_mode, "Dark").clicked() {
-                        self.settings.dark_mode = true;
-                        self.apply_theme(ctx);
-                        self.settings_changed = true;
-                    }
-                    if ui.selectable_label(!self.settings.dark_mode, "Light").clicked() {
-                        self.settings.dark_mode = false;
-                        self.apply_theme(ctx);
-                        self.settings_changed = true;
+        match command {
+            "goto" | "g" => {
+                let visible = self.visible_note_indices();
+                let idx = match arg.parse::<usize>() {
+                    Ok(n) if (1..=visible.len()).contains(&n) => visible[n - 1],
+                    Ok(n) => return Err(format!("There is no note {} (the list has {})", n, visible.len())),
+                    Err(_) => {
+                        let id = u128::from_str_radix(arg, 16).map_err(|_| format!("Expected a note number or id, got \"{}\"", arg))?;
+                        self.notes.iter().position(|n| n.id == id).ok_or(format!("No note with id {}", arg))?
                     }
-                });
+                };
+                if self.prepare_switch(idx) {
+                    self.pick_note(idx);
+                }
+            }
+            "new" => self.add_note(),
+            "delete" => {
+                let idx = selected.ok_or("No note selected")?;
+                if self.notes[idx].editing {
+                    return Err("Save or close the note before deleting it".to_owned());
+                }
+                self.delete_note(idx);
+            }
+            "tag" | "untag" => {
+                let idx = selected.ok_or("No note selected")?;
+                let tag = arg.trim_start_matches('#');
+                if tag.is_empty() {
+                    return Err(format!("Usage: {} <name>", command));
+                }
+                let note = &mut self.notes[idx];
+                let has_tag = note.tags.iter().any(|t| t == tag);
+                if command == "tag" && !has_tag {
+                    note.tags.push(tag.to_owned());
+                } else if command == "untag" && has_tag {
+                    note.tags.retain(|t| t != tag);
+                } else {
+                    return Ok(());
+                }
+                note.modified = current_unix();
+                self.dirty = true;
+            }
+            "pin" => self.apply_note_action(selected.ok_or("No note selected")?, NoteAction::TogglePin),
+            "archive" => self.apply_note_action(selected.ok_or("No note selected")?, NoteAction::ToggleArchive),
+            "" => {}
+            _ => return Err(format!("Unknown command: {}", command)),
+        }
+        Ok(())
+    }
+
+    fn show_command_bar(&mut self, ctx: &egui::Context) {
+        let Some(input) = &mut self.command_input else {
+            return;
+        };
+
+        let mut run = false;
+        let mut close = false;
 
+        egui::TopBottomPanel::bottom("command_bar")
+            .frame(egui::Frame::default()
+                .fill(ctx.style().visuals.panel_fill)
+                .inner_margin(egui::Margin { top: 6, bottom: 6, left: 10, right: 10 })
+            )
+            .show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    ui.label("Font size:");
-                    let mut font_size = self.settings.font_size;
-                    if ui.add(egui::Slider::new(&mut font_size, 12.0..=24.0).step_by(1.0)).changed() {
-                        self.settings.font_size = font_size;
-                        self.apply_font_settings(ctx);
-                        self.settings_changed = true;
-                    }
+                    ui.label(egui::RichText::new(":").monospace());
+                    let response = ui.add(
+                        egui::TextEdit::singleline(input)
+                            .font(egui::TextStyle::Monospace)
+                            .hint_text("goto 5, new, delete, tag work, untag work, pin, archive")
+                            .desired_width(f32::INFINITY),
+                    );
+                    response.request_focus();
+                    run = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    close = ui.input(|i| i.key_pressed(egui::Key::Escape));
                 });
+                if let Some(error) = &self.command_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
             });
 
-            ui.add_space(10.0);
+        if run {
+            let input = self.command_input.take().unwrap_or_default();
+            if let Err(error) = self.run_command(&input) {
+                self.command_error = Some(error);
+                self.command_input = Some(input);
+            }
+        } else if close {
+            self.command_input = None;
+            self.command_error = None;
+        }
+    }
 
-            ui.group(|ui| {
-                ui.label(egui::RichText::new("Editor").size(18.0));
-                ui.add_space(5.0);
+    fn template_body(&self, name: &str) -> Option<String> {
+        self.settings
+            .templates
+            .iter()
+            .find(|t| !name.is_empty() && t.name == name)
+            .map(|t| t.body.replace("{date}", &Local::now().format("%Y-%m-%d").to_string()))
+    }
 
-                let mut auto_save = self.settings.auto_save;
-                if ui.checkbox(&mut auto_save, "Auto-save notes").changed() {
-                    self.settings.auto_save = auto_save;
-                    self.settings_changed = true;
-                }
+    fn open_daily_note(&mut self, ctx: &egui::Context) {
+        let now = Local::now();
+        let items: Vec<Item> = StrftimeItems::new(&self.settings.daily_note_title).collect();
+        let title = if items.iter().any(|item| matches!(item, Item::Error)) {
+            self.settings.daily_note_title.clone()
+        } else {
+            now.format_with_items(items.into_iter()).to_string()
+        };
 
-                let mut drag_and_drop = self.settings.drag_and_drop;
-                if ui.checkbox(&mut drag_and_drop, "Enable Drag and Drop").changed() {
-                    self.settings.drag_and_drop = drag_and_drop;
-                    self.settings_changed = true;
-                }
+        let idx = match self.notes.iter().position(|n| n.title == title) {
+            Some(idx) => idx,
+            None => {
+                let mut note = Note::new(rand::random::<u128>());
+                note.title = title;
+                note.body = self.template_body(&self.settings.daily_note_template).unwrap_or_default();
+                self.notes.insert(0, note);
+                self.selected = self.selected.map(|idx| idx + 1);
+                self.dirty = true;
+                0
+            }
+        };
+        self.current_view = AppView::Notes;
+        if !self.prepare_switch(idx) {
+            return;
+        }
+        self.selected = Some(idx);
 
-                let mut show_word_count = self.settings.show_word_count;
-                if ui.checkbox(&mut show_word_count, "Show word count").changed() {
-                    self.settings.show_word_count = show_word_count;
-                    self.settings_changed = true;
+        let note = &mut self.notes[idx];
+        if !note.start_editing() {
+            return;
+        }
+        if !note.body.is_empty() {
+            if !note.body.ends_with('\n') {
+                note.body.push('\n');
+            }
+            note.body.push('\n');
+        }
+        note.body.push_str(&format!("**{}** ", now.format("%H:%M")));
+        note.modified = current_unix();
+        if self.settings.auto_save {
+            self.dirty = true;
+        }
+
+        let body_id = egui::Id::new("note_body").with(note.id);
+        let end = note.body.chars().count();
+        let mut state = egui::text_edit::TextEditState::load(ctx, body_id).unwrap_or_default();
+        state.cursor.set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(end))));
+        state.store(ctx, body_id);
+        ctx.memory_mut(|m| m.request_focus(body_id));
+    }
+
+    fn apply_launch_action(&mut self, ctx: &egui::Context, action: LaunchAction) {
+        self.current_view = AppView::Notes;
+        match action {
+            LaunchAction::Open(title) => {
+                let position = self
+                    .notes
+                    .iter()
+                    .position(|n| n.title == title)
+                    .or_else(|| self.notes.iter().position(|n| n.title.eq_ignore_ascii_case(&title)));
+                match position {
+                    Some(idx) => self.selected = Some(idx),
+                    None => eprintln!("No note titled \"{}\"", title),
                 }
-            });
+            }
+            LaunchAction::OpenId(id) => match self.notes.iter().position(|n| n.id == id) {
+                Some(idx) => self.selected = Some(idx),
+                None => eprintln!("No note with id {:032x}, it may have been deleted", id),
+            },
+            LaunchAction::Import(path) if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) => {
+                self.import_note_bundle(&path);
+            }
+            LaunchAction::Import(path) => match note_from_file(&path) {
+                Ok(note) => {
+                    self.notes.insert(0, note);
+                    self.selected = Some(0);
+                    self.dirty = true;
+                }
+                Err(e) => eprintln!("Failed to import {}: {}", path.display(), e),
+            },
+            LaunchAction::Capture => self.quick_capture(ctx),
+        }
+    }
 
-            ui.add_space(10.0);
-            ui.group(|ui| {
-                ui.label(egui::RichText::new("Storage Information").size(18.0));
-                ui.add_space(5.0);
-                ui.group(|ui| {
-                    ui.label("Notes stored at:");
-                    ui.label(format!("{}", self.data_path));
-                });
-                ui.group(|ui| {
-                    ui.label("Settings stored at:");
-                    ui.label(format!("{}", self.settings_path));
-                });
-                ui.label(format!("Total notes: {}", self.notes.len()));
-            });
+    fn add_tag_to_checked(&mut self, tag: &str) {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return;
+        }
+        for note in self.notes.iter_mut().filter(|n| self.checked.contains(&n.id)) {
+            if !note.tags.iter().any(|t| t == tag) {
+                note.tags.push(tag.to_owned());
+                note.modified = current_unix();
+                self.dirty = true;
+            }
+        }
+    }
 
-            ui.add_space(20.0);
+    fn remove_tag_from_checked(&mut self, tag: &str) {
+        let tag = tag.trim();
+        for note in self.notes.iter_mut().filter(|n| self.checked.contains(&n.id)) {
+            if note.tags.iter().any(|t| t == tag) {
+                note.tags.retain(|t| t != tag);
+                note.modified = current_unix();
+                self.dirty = true;
+            }
+        }
+    }
 
-            ui.separator();
-            ui.horizontal(|ui| {
-                if ui.button("Reset to Defaults").clicked() {
-                    self.settings = AppSettings::default();
-                    self.apply_theme(ctx);
-                    self.apply_font_settings(ctx);
-                    self.settings_changed = true;
-                }
-            });
-        });
+    fn rename_tag(&mut self, from: &str, to: &str) {
+        let to = to.trim();
+        if to.is_empty() || from == to {
+            return;
+        }
+        for note in self.notes.iter_mut().filter(|n| n.tags.iter().any(|t| t == from)) {
+            note.tags.retain(|t| t != from && t != to);
+            note.tags.push(to.to_owned());
+            if let Some(pin) = note.pinned_in.iter_mut().find(|t| **t == from.to_lowercase()) {
+                *pin = to.to_lowercase();
+            }
+            note.modified = current_unix();
+            self.dirty = true;
+        }
+        self.save_notes();
     }
 
-    fn get_word_count(text: &str) -> usize {
-        text.split_whitespace().count()
+    fn delete_tag(&mut self, tag: &str) {
+        for note in self.notes.iter_mut().filter(|n| n.tags.iter().any(|t| t == tag)) {
+            note.tags.retain(|t| t != tag);
+            note.pinned_in.retain(|t| *t != tag.to_lowercase());
+            note.modified = current_unix();
+            self.dirty = true;
+        }
+        self.save_notes();
     }
-}
 
-impl eframe::App for NotesApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        static mut THEME_APPLIED: bool = false;
-        static mut FONT_SET: bool = false;
+    fn commit_preview(&mut self) {
+        let Some(idx) = self.preview_selection.and_then(|id| self.notes.iter().position(|n| n.id == id)) else {
+            return;
+        };
+        if self.prepare_switch(idx) {
+            self.pick_note(idx);
+        }
+        self.preview_selection = None;
+    }
 
-        unsafe {
-            if !THEME_APPLIED {
-                self.apply_theme(ctx);
-                THEME_APPLIED = true;
+    fn show_search_preview(&self, ui: &mut egui::Ui, idx: usize) {
+        let note = &self.notes[idx];
+        ui.horizontal(|ui| {
+            ui.label("");
+            if let Some(icon) = &note.icon {
+                ui.label(egui::RichText::new(icon).heading());
             }
+            ui.label(egui::RichText::new(&note.title).heading());
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(egui::RichText::new("Preview — Enter to open").size(10.0).weak());
+            });
+        });
+        ui.separator();
+        egui::ScrollArea::vertical().id_salt(("note_preview_scroll", note.id)).show(ui, |ui| {
+            ui.set_max_width(ui.available_width().min(self.settings.max_editor_width));
+            markdown::show(ui, &note.body);
+        });
+    }
 
-            if !FONT_SET {
-                self.apply_font_settings(ctx);
-                FONT_SET = true;
+    fn show_notes_grid(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search notes").desired_width(250.0));
+            if !self.search.is_empty() && ui.button("✖").on_hover_text("Clear search").clicked() {
+                self.search.clear();
             }
+        });
+        ui.add_space(8.0);
+
+        let visible = self.visible_note_indices();
+        if visible.is_empty() {
+            ui.label(if self.notes.is_empty() { "No notes yet" } else { "No notes match the search" });
+            return;
         }
 
-        egui::TopBottomPanel::top("top_panel")
-            .frame(egui::Frame::default()
-                .fill(ctx.style().visuals.panel_fill)
-                .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 10 })
-                .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
-            )
-            .show(ctx, |ui| {
-                ui.horizontal(|ui| {
-                    if ui.selectable_label(self.current_view == AppView::Notes, "Notes").clicked() {
-                        self.current_view = AppView::Notes;
+        self.track_selected_position(&visible);
+
+        let mut open: Option<usize> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = egui::vec2(10.0, 10.0);
+                for idx in visible {
+                    let note = &self.notes[idx];
+                    let selected = self.selected == Some(idx);
+                    let card = egui::Frame::group(ui.style())
+                        .fill(ui.visuals().faint_bg_color)
+                        .inner_margin(egui::Margin::same(8))
+                        .show(ui, |ui| {
+                            let inner = GRID_CARD_SIZE - egui::vec2(16.0, 16.0);
+                            ui.set_min_size(inner);
+                            ui.set_max_size(inner);
+                            ui.vertical(|ui| {
+                                let mut title = note.title.clone();
+                                if let Some(icon) = &note.icon {
+                                    title = format!("{} {}", icon, title);
+                                }
+                                if note.locked {
+                                    title = format!("🔒 {}", title);
+                                }
+                                if note.is_expired() {
+                                    title = format!("⌛ {}", title);
+                                }
+                                if self.pinned_here(note) {
+                                    title = format!("📌 {}", title);
+                                }
+                                ui.add(egui::Label::new(egui::RichText::new(title).strong()).truncate());
+                                ui.add(
+                                    egui::Label::new(egui::RichText::new(body_preview(&note.body, 120)).size(11.0).weak())
+                                        .wrap(),
+                                );
+                            });
+                        });
+                    let response = card.response.interact(egui::Sense::click());
+                    if selected || response.hovered() {
+                        let stroke = if selected {
+                            ui.visuals().selection.stroke
+                        } else {
+                            ui.visuals().widgets.hovered.bg_stroke
+                        };
+                        ui.painter().rect_stroke(response.rect, 6.0, stroke, egui::StrokeKind::Inside);
                     }
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
-                        if ui.selectable_label(self.current_view == AppView::Settings, "⚙").clicked() {
-                            self.current_view = AppView::Settings;
-                        }
-                    });
-                });
+                    if selected && self.scroll_to_selected {
+                        response.scroll_to_me(None);
+                    }
+                    if response.clicked() {
+                        open = Some(idx);
+                    }
+                }
             });
+        });
 
-        match self.current_view {
-            AppView::Settings => {
-                egui::CentralPanel::default()
-                    .frame(egui::Frame::default()
-                        .fill(ctx.style().visuals.panel_fill)
-                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 20, right: 20 })
-                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
-                    )
-                    .show(ctx, |ui| {
-                        self.show_settings_page(ctx, ui);
-                    });
+        if let Some(idx) = open {
+            if self.prepare_switch(idx) {
+                self.pick_note(idx);
             }
-            AppView::Notes => {
-                egui::SidePanel::left("left_panel")
-                    .frame(egui::Frame::default()
-                        .fill(ctx.style().visuals.panel_fill)
-                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 10 })
-                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
-                    )
-                    .min_width(150.0).show(ctx, |ui| {
-                        ui.vertical(|ui| {
+            self.settings.notes_layout = NotesLayout::List;
+            self.settings_changed = true;
+        }
+    }
+
+    fn show_tags_page(&mut self, ui: &mut egui::Ui) {
+        let mut counts: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+        for tag in self.notes.iter().flat_map(|n| n.tags.iter()) {
+            *counts.entry(tag.as_str()).or_default() += 1;
+        }
+        let mut tags: Vec<(String, usize)> = counts.into_iter().map(|(tag, count)| (tag.to_owned(), count)).collect();
+        match self.settings.tag_sort {
+            TagSort::Name => tags.sort_by_key(|(tag, _)| tag.to_lowercase()),
+            TagSort::Frequency => tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase()))),
+        }
+
+        ui.heading("Tags");
+        ui.horizontal(|ui| {
+            ui.label("Sort by:");
+            for sort in TagSort::ALL {
+                if ui.selectable_label(self.settings.tag_sort == sort, sort.label()).clicked() {
+                    self.settings.tag_sort = sort;
+                    self.settings_changed = true;
+                }
+            }
+        });
+        ui.separator();
+
+        if tags.is_empty() {
+            ui.label("No tags yet — select notes with Select → Add tag, or type :tag <name> in the notes list");
+            return;
+        }
+
+        let mut rename: Option<(String, String)> = None;
+        let mut delete: Option<String> = None;
+        let mut cancel_rename = false;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("tags_grid").num_columns(3).striped(true).spacing(egui::vec2(16.0, 6.0)).show(ui, |ui| {
+                for (tag, count) in &tags {
+                    match &mut self.tag_rename {
+                        Some((from, to)) if from == tag => {
+                            let response = ui.add(egui::TextEdit::singleline(to).desired_width(160.0));
+                            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            ui.label(format!("{} note{}", count, if *count == 1 { "" } else { "s" }));
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(!to.trim().is_empty(), egui::Button::new("Rename")).clicked() || submitted {
+                                    rename = Some((from.clone(), to.clone()));
+                                }
+                                cancel_rename = ui.button("Cancel").clicked();
+                            });
+                        }
+                        _ => {
+                            ui.label(format!("#{}", tag));
+                            ui.label(format!("{} note{}", count, if *count == 1 { "" } else { "s" }));
                             ui.horizontal(|ui| {
-                                if self.current_view == AppView::Notes {
-                                    if ui.button("New").clicked() {
-                                        self.add_note();
+                                if self.tag_delete.as_ref() == Some(tag) {
+                                    ui.label(format!("Remove from {} note{}?", count, if *count == 1 { "" } else { "s" }));
+                                    if ui.button("Delete").clicked() {
+                                        delete = Some(tag.clone());
+                                    }
+                                    if ui.button("Cancel").clicked() {
+                                        self.tag_delete = None;
+                                    }
+                                } else {
+                                    if ui.button("Rename").clicked() {
+                                        self.tag_rename = Some((tag.clone(), tag.clone()));
+                                        self.tag_delete = None;
                                     }
                                     if ui.button("Delete").clicked() {
-                                        self.delete_selected();
+                                        self.tag_delete = Some(tag.clone());
+                                        self.tag_rename = None;
                                     }
                                 }
                             });
-                            ui.add_space(2.0);
-                            ui.separator();
-                            ui.add_space(5.0);
-                            ui.horizontal(|ui| {
-                                ui.label("Search:");
-                                ui.text_edit_singleline(&mut self.search);
-                            });
-                            ui.add_space(2.0);
-                            ui.separator();
-                            ui.add_space(2.0);
-                            
-
-                            let filtered_notes: Vec<(usize, String, u128)> = self
-                                .notes
-                                .iter()
-                                .enumerate()
-                                .filter(|(_, n)| {
-                                    let q = self.search.to_lowercase();
-                                    q.is_empty()
-                                        || n.title.to_lowercase().contains(&q)
-                                        || n.body.to_lowercase().contains(&q)
-                                })
-                                .map(|(i, n)| (i, n.title.clone(), n.id))
-                                .collect();
-
-                            let mut to_select: Option<usize> = None;
-                            let mut move_from_to: Option<(usize, usize)> = None;
-
-                            let enable_dnd = self.search.is_empty();
-
-                            let line_height = ui.text_style_height(&egui::TextStyle::Body);
-                            let spacing = ui.spacing().item_spacing.y;
-                            let bottom_content_height = line_height + if enable_dnd { line_height + spacing } else { 0.0 } + spacing * 2.0;
-
-                            let available_height = ui.available_height() - bottom_content_height;
-
-                            let mut item_rects: Vec<(usize, usize, egui::Rect)> = Vec::new();
-
-                            egui::ScrollArea::vertical()
-                                .max_height(available_height)
-                                .show(ui, |ui| {
-                                    for (display_idx, (original_idx, title, _id)) in filtered_notes.iter().enumerate() {
-                                        let selected = Some(*original_idx) == self.selected;
-
-                                        if enable_dnd && self.settings.drag_and_drop {
-                                            ui.horizontal(|ui| {
-                                                let base_font_size = 14.0;
-                                                let scale_factor = self.settings.font_size / base_font_size;
-                                                let handle_width = (10.0 * scale_factor).max(8.0);
-                                                let handle_height = (16.0 * scale_factor).max(12.0);
-                                                let handle_size = egui::vec2(handle_width, handle_height);
-                                                
-                                                let handle_response = ui.allocate_response(
-                                                    handle_size,
-                                                    egui::Sense::click_and_drag()
-                                                );
-
-                                                let painter = ui.painter();
-                                                let handle_rect = handle_response.rect;
-                                                let handle_color = if handle_response.hovered() {
-                                                    ui.visuals().text_color()
-                                                } else {
-                                                    ui.visuals().weak_text_color()
-                                                };
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
 
-                                                let line_spacing = (4.0 * scale_factor).max(2.0);
-                                                let line_width = (8.0 * scale_factor).max(6.0);
-                                                let line_thickness = (1.5 * scale_factor).max(1.0);
-                                                
-                                                let center_x = handle_rect.center().x;
-                                                let center_y = handle_rect.center().y;
-                                                
-                                                for i in 0..3 {
-                                                    let y = center_y + (i as f32 - 1.0) * line_spacing;
-                                                    painter.line_segment(
-                                                        [
-                                                            egui::pos2(center_x - line_width / 2.0, y),
-                                                            egui::pos2(center_x + line_width / 2.0, y)
-                                                        ],
-                                                        egui::Stroke::new(line_thickness, handle_color)
-                                                    );
-                                                }
+        if let Some((from, to)) = rename {
+            self.rename_tag(&from, &to);
+            self.tag_rename = None;
+        }
+        if cancel_rename {
+            self.tag_rename = None;
+        }
+        if let Some(tag) = delete {
+            self.delete_tag(&tag);
+            self.tag_delete = None;
+        }
+    }
 
-                                                let remaining_width = ui.available_width();
-                                                let mut current_selection = if selected { Some(*original_idx) } else { None };
-                                                let response = ui.selectable_value(&mut current_selection, Some(*original_idx), format!("{}", title));
-                                                if response.clicked() {
-                                                    to_select = Some(*original_idx);
-                                                }
+    fn show_batch_tag_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} selected", self.checked.len()));
+        if self.checked.is_empty() {
+            return;
+        }
 
-                                                let item_height = (20.0 * scale_factor).max(16.0);
-                                                let full_rect = egui::Rect::from_min_size(
-                                                    handle_rect.min,
-                                                    egui::vec2(handle_rect.width() + remaining_width, item_height)
-                                                );
-                                                item_rects.push((display_idx, *original_idx, full_rect));
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.batch_tag_input).hint_text("Tag").desired_width(80.0));
+            if ui.button("Add tag").clicked() {
+                let tag = std::mem::take(&mut self.batch_tag_input);
+                self.add_tag_to_checked(&tag);
+            }
+            if ui.button("Remove tag").clicked() {
+                let tag = std::mem::take(&mut self.batch_tag_input);
+                self.remove_tag_from_checked(&tag);
+            }
+        });
 
-                                                if handle_response.drag_started() {
-                                                    self.dragging = Some(*original_idx);
-                                                    self.drag_start_pos = ctx.pointer_latest_pos();
-                                                }
+        let checked_notes: Vec<&Note> = self.notes.iter().filter(|n| self.checked.contains(&n.id)).collect();
+        let mut tags: Vec<String> = checked_notes.iter().flat_map(|n| n.tags.iter().cloned()).collect();
+        tags.sort();
+        tags.dedup();
+        let common: Vec<bool> = tags
+            .iter()
+            .map(|tag| checked_notes.iter().all(|n| n.tags.contains(tag)))
+            .collect();
 
-                                                if let Some(dragging_idx) = self.dragging {
-                                                    if dragging_idx == *original_idx {
-                                                        if let (Some(pointer_pos), Some(start_pos)) = (ctx.pointer_latest_pos(), self.drag_start_pos) {
-                                                            let offset = pointer_pos - start_pos;
-                                                            let dragged_rect = full_rect.translate(egui::vec2(0.0, offset.y));
-
-                                                            let painter = ui.painter();
-                                                            painter.rect_filled(
-                                                                dragged_rect,
-                                                                4.0,
-                                                                egui::Color32::from_rgba_premultiplied(30, 30, 30, 160)
-                                                            );
-                                                            painter.text(
-                                                                dragged_rect.left_center() + egui::vec2(20.0 * scale_factor, 0.0),
-                                                                egui::Align2::LEFT_CENTER,
-                                                                title,
-                                                                egui::FontId::proportional(self.settings.font_size),
-                                                                egui::Color32::WHITE
-                                                            );
-                                                        }
-                                                    }
-                                                }
-                                            });
+        ui.horizontal_wrapped(|ui| {
+            for (tag, common) in tags.iter().zip(common) {
+                let hint = if common { "Remove from all selected notes" } else { "Add to all selected notes" };
+                if ui.selectable_label(common, format!("#{}", tag)).on_hover_text(hint).clicked() {
+                    if common {
+                        self.remove_tag_from_checked(tag);
+                    } else {
+                        self.add_tag_to_checked(tag);
+                    }
+                }
+            }
+        });
+    }
 
-                                            if self.dragging.is_some() {
-                                                if let Some(pointer_pos) = ctx.pointer_latest_pos() {
-                                                    let painter = ui.painter();
-                                                    for (_didx, target_orig_idx, rect) in item_rects.iter() {
-                                                        if *target_orig_idx != self.dragging.unwrap() {
-                                                            if rect.contains(pointer_pos) {
-                                                                let y = if pointer_pos.y < rect.center().y {
-                                                                    rect.top()
-                                                                } else {
-                                                                    rect.bottom()
-                                                                };
-                                                                painter.hline(
-                                                                    rect.x_range(),
-                                                                    y,
-                                                                    egui::Stroke::new(2.0, egui::Color32::GRAY)
-                                                                );
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        } else {
-                                            let mut current_selection = if selected { Some(*original_idx) } else { None };
-                                            if ui.selectable_value(&mut current_selection, Some(*original_idx), format!("{}", title)).clicked() {
-                                                to_select = Some(*original_idx);
-                                            }
-                                        }
-                                    }
-                                });
+    fn show_check_box(&mut self, ui: &mut egui::Ui, idx: usize) {
+        let id = self.notes[idx].id;
+        let mut checked = self.checked.contains(&id);
+        if ui.checkbox(&mut checked, "").changed() {
+            if checked {
+                self.checked.insert(id);
+            } else {
+                self.checked.remove(&id);
+            }
+        }
+    }
 
-                            if self.dragging.is_some() && ctx.input(|i| i.pointer.any_released()) {
-                                if let Some(pointer_pos) = ctx.pointer_latest_pos() {
-                                    let mut found: Option<usize> = None;
-                                    for (display_idx, _, rect) in item_rects.iter() {
-                                        if rect.contains(pointer_pos) {
-                                            let desired = if pointer_pos.y < rect.center().y { *display_idx } else { *display_idx + 1 };
-                                            found = Some(desired);
-                                            break;
-                                        }
-                                    }
+    fn selected_note_editing(&self) -> bool {
+        self.selected.and_then(|idx| self.notes.get(idx)).is_some_and(|n| n.editing)
+    }
 
-                                    let desired = if let Some(d) = found {
-                                        d
-                                    } else if !item_rects.is_empty() {
-                                        let first_rect = &item_rects[0].2;
-                                        let last_rect = &item_rects[item_rects.len() - 1].2;
-                                        if pointer_pos.y < first_rect.center().y {
-                                            0
-                                        } else if pointer_pos.y > last_rect.center().y {
-                                            item_rects.len()
-                                        } else {
-                                            item_rects.len()
-                                        }
-                                    } else {
-                                        0
-                                    };
-                                    move_from_to = Some((self.dragging.unwrap(), desired));
-                                }
+    fn delete_selected(&mut self) {
+        if let Some(idx) = self.selected {
+            self.delete_note(idx);
+        }
+    }
 
-                                self.dragging = None;
-                                self.drag_start_pos = None;
-                            }
+    fn delete_note(&mut self, idx: usize) {
+        match self.notes.get(idx) {
+            Some(note) if note.locked => self.locked_delete = Some(note.id),
+            Some(note) if !note.editing => self.remove_note(idx),
+            _ => {}
+        }
+    }
 
-                            if let Some((from, to)) = move_from_to {
-                                self.move_note(from, to);
-                            }
+    fn remove_note(&mut self, idx: usize) {
+        let selected_id = self.selected.filter(|&s| s != idx).and_then(|s| self.notes.get(s)).map(|n| n.id);
+        let note = self.notes.remove(idx);
+        self.orphaned_attachments.extend(note.attachments);
+        if self.history.remove(&note.id).is_some() {
+            self.save_history();
+        }
+        self.selected = selected_id
+            .and_then(|id| self.notes.iter().position(|n| n.id == id))
+            .or(if self.notes.is_empty() { None } else { Some(0) });
+        self.dirty = true;
+    }
 
-                            if let Some(s) = to_select {
-                                self.selected = Some(s);
-                            }
+    fn apply_note_action(&mut self, idx: usize, action: NoteAction) {
+        let Some(note) = self.notes.get_mut(idx) else {
+            return;
+        };
+        match action {
+            NoteAction::Edit => {
+                if self.prepare_switch(idx) {
+                    self.notes[idx].start_editing();
+                    self.selected = Some(idx);
+                }
+            }
+            NoteAction::Duplicate => {
+                let mut copy = note.clone();
+                copy.id = rand::random::<u128>();
+                copy.title = format!("{} (copy)", note.title);
+                copy.editing = false;
+                copy.backup = None;
+                copy.locked = false;
+                copy.created = current_unix();
+                copy.modified = current_unix();
+                self.notes.insert(idx + 1, copy);
+                self.selected = self.selected.map(|selected| if selected > idx { selected + 1 } else { selected });
+                self.dirty = true;
+                if self.prepare_switch(idx + 1) {
+                    self.selected = Some(idx + 1);
+                }
+            }
+            NoteAction::TogglePin => {
+                note.pinned = !note.pinned;
+                self.dirty = true;
+            }
+            NoteAction::ToggleTagPin => {
+                let (tags, _) = parse_search_query(&self.search);
+                let Some(tag) = tags.into_iter().next() else {
+                    return;
+                };
+                match note.pinned_in.iter().position(|t| *t == tag) {
+                    Some(pos) => {
+                        note.pinned_in.remove(pos);
+                    }
+                    None => note.pinned_in.push(tag),
+                }
+                self.dirty = true;
+            }
+            NoteAction::ToggleLock => {
+                if !note.editing {
+                    note.locked = !note.locked;
+                    self.dirty = true;
+                }
+            }
+            NoteAction::ToggleArchive => {
+                // Restoring an expired note drops the date, or it would be archived again straight away.
+                if note.is_expired() {
+                    note.expires_at = None;
+                }
+                note.archived = !note.archived;
+                note.pinned = false;
+                note.pinned_in.clear();
+                self.dirty = true;
+            }
+            NoteAction::Delete => self.delete_note(idx),
+        }
+    }
+
+    fn note_context_menu(&self, ui: &mut egui::Ui, idx: usize, action: &mut Option<(usize, NoteAction)>) {
+        let note = &self.notes[idx];
+        let mut chosen = None;
+        let edit = ui.add_enabled(!note.locked, egui::Button::new("Edit"));
+        if edit.on_disabled_hover_text("Unlock this note to edit it").clicked() {
+            chosen = Some(NoteAction::Edit);
+        }
+        if ui.button("Duplicate").clicked() {
+            chosen = Some(NoteAction::Duplicate);
+        }
+        if ui.button(if note.pinned { "Unpin" } else { "Pin" }).clicked() {
+            chosen = Some(NoteAction::TogglePin);
+        }
+        if let Some(tag) = parse_search_query(&self.search).0.into_iter().next() {
+            let label = if note.pinned_in.contains(&tag) {
+                format!("Unpin from #{}", tag)
+            } else {
+                format!("Pin in #{}", tag)
+            };
+            if ui.button(label).on_hover_text("Pinned only while filtering by this tag").clicked() {
+                chosen = Some(NoteAction::ToggleTagPin);
+            }
+        }
+        if ui.add_enabled(!note.editing, egui::Button::new(if note.locked { "Unlock" } else { "Lock" })).clicked() {
+            chosen = Some(NoteAction::ToggleLock);
+        }
+        if ui.button(if note.archived { "Unarchive" } else { "Archive" }).clicked() {
+            chosen = Some(NoteAction::ToggleArchive);
+        }
+        if ui.button("Copy title").clicked() {
+            ui.ctx().copy_text(note.title.clone());
+            ui.close();
+        }
+        ui.separator();
+        let delete = ui.add_enabled(!note.editing, egui::Button::new("Delete"));
+        if delete.on_disabled_hover_text("Finish editing before deleting this note").clicked() {
+            chosen = Some(NoteAction::Delete);
+        }
+        if let Some(chosen) = chosen {
+            *action = Some((idx, chosen));
+            ui.close();
+        }
+    }
+
+    fn delete_checked(&mut self) {
+        let selected_id = self.selected.and_then(|idx| self.notes.get(idx)).map(|n| n.id);
+        let mut history_changed = false;
+        self.notes.retain(|n| {
+            if n.editing || n.locked || !self.checked.contains(&n.id) {
+                return true;
+            }
+            history_changed |= self.history.remove(&n.id).is_some();
+            self.orphaned_attachments.extend(n.attachments.iter().cloned());
+            false
+        });
+        if history_changed {
+            self.save_history();
+        }
+        self.checked.clear();
+        self.selected = selected_id
+            .and_then(|id| self.notes.iter().position(|n| n.id == id))
+            .or(if self.notes.is_empty() { None } else { Some(0) });
+        self.dirty = true;
+    }
+
+    fn save_notes(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if self.settings.trim_trailing_whitespace {
+            for note in self.notes.iter_mut().filter(|n| !n.editing) {
+                note.body = trim_trailing_whitespace(&note.body);
+            }
+        }
+
+        let held_back = self.notes.iter().any(|n| n.no_autosave && n.editing);
+        let notes: std::borrow::Cow<[Note]> = if held_back {
+            self.notes
+                .iter()
+                .map(|n| {
+                    let mut n = n.clone();
+                    if let (true, true, Some(backup)) = (n.no_autosave, n.editing, &n.backup) {
+                        n.body = backup.clone();
+                    }
+                    n
+                })
+                .collect()
+        } else {
+            std::borrow::Cow::Borrowed(&self.notes)
+        };
+
+        match self.store.save(&notes) {
+            Err(e) => {
+                let error = e.to_string();
+                let attempts = self.save_failure.as_ref().map_or(0, |f| f.attempts) + 1;
+                if self.save_failure.as_ref().is_none_or(|f| f.error != error) {
+                    eprintln!("Failed to save notes: {}", error);
+                }
+                let delay = save_retry_delay(attempts, self.settings.save_retry_max_seconds);
+                self.save_failure = Some(SaveFailure { error, attempts, retry_at: std::time::Instant::now() + delay });
+            }
+            Ok(changed) => {
+                self.save_failure = None;
+                self.dirty = false;
+                self.last_saved = Some(std::time::Instant::now());
+                self.record_history(changed);
+            }
+        }
+    }
+
+    // Takes the notes whose saved body just changed. A note still being edited gets its
+    // version once editing ends; the body it replaced is kept when it has no history yet.
+    fn record_history(&mut self, changed: BodyChanges) {
+        let mut added = false;
+        for (id, previous) in changed {
+            if self.history.get(&id).is_none_or(Vec::is_empty) {
+                if let Some(previous) = previous {
+                    self.history.insert(id, vec![previous]);
+                    added = true;
+                }
+            }
+            self.history_pending.insert(id);
+        }
+
+        let notes = &self.notes;
+        let ready: Vec<u128> = self
+            .history_pending
+            .iter()
+            .copied()
+            .filter(|id| notes.iter().find(|n| n.id == *id).is_none_or(|n| !n.editing))
+            .collect();
+        for id in ready {
+            self.history_pending.remove(&id);
+            let Some(note) = self.notes.iter().find(|n| n.id == id) else {
+                continue;
+            };
+            let versions = self.history.entry(id).or_default();
+            if versions.last().is_some_and(|v| v.body == note.body) {
+                continue;
+            }
+            versions.push(NoteVersion { saved: current_unix(), body: note.body.clone() });
+            if versions.len() > MAX_HISTORY_VERSIONS {
+                versions.remove(0);
+            }
+            added = true;
+        }
+
+        if added {
+            self.save_history();
+        }
+    }
+
+    fn save_history(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = save_history(&self.history_path, &self.history) {
+            eprintln!("Failed to save history: {}", e);
+        }
+    }
+
+    fn show_history_window(&mut self, ctx: &egui::Context) {
+        let Some(note_id) = self.history_note else {
+            return;
+        };
+        let Some(note_idx) = self.notes.iter().position(|n| n.id == note_id) else {
+            self.history_note = None;
+            return;
+        };
 
-                            ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                                ui.label(format!("{} notes", self.notes.len()));
-                                if enable_dnd && self.settings.drag_and_drop {
-                                    ui.label(egui::RichText::new("Drag handles to reorder").size(10.0));
+        let versions = self.history.get(&note_id).cloned().unwrap_or_default();
+        let mut close = false;
+        let mut restore: Option<String> = None;
+
+        let modal = egui::Modal::new(egui::Id::new("history_modal")).show(ctx, |ui| {
+            ui.set_width(500.0);
+            ui.heading(format!("History of {}", self.notes[note_idx].title));
+            ui.separator();
+
+            if versions.is_empty() {
+                ui.label("No saved versions yet");
+            } else {
+                ui.horizontal_top(|ui| {
+                    egui::ScrollArea::vertical().id_salt("history_versions").max_height(300.0).show(ui, |ui| {
+                        ui.vertical(|ui| {
+                            for (i, version) in versions.iter().enumerate().rev() {
+                                let dt: DateTime<Local> = Local.timestamp_opt(version.saved as i64, 0).unwrap();
+                                let label = dt.format("%d-%m-%Y %H:%M:%S").to_string();
+                                if ui.selectable_label(self.history_preview == Some(i), label).clicked() {
+                                    self.history_preview = Some(i);
                                 }
-                                ui.separator();
-                            });
+                            }
                         });
                     });
+                    ui.separator();
+                    egui::ScrollArea::vertical().id_salt("history_preview").max_height(300.0).show(ui, |ui| {
+                        match self.history_preview.and_then(|i| versions.get(i)) {
+                            Some(version) => {
+                                ui.label(&version.body);
+                            }
+                            None => {
+                                ui.label("Select a version to preview it");
+                            }
+                        }
+                    });
+                });
+            }
 
-                egui::CentralPanel::default()
-                    .frame(egui::Frame::default()
-                        .fill(ctx.style().visuals.panel_fill)
-                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 15 })
-                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
-                    )
-                    .show(ctx, |ui| {
-                        if let Some(idx) = self.selected {
-                            if idx < self.notes.len() {
-                                let note = &mut self.notes[idx];
+            ui.separator();
+            ui.horizontal(|ui| {
+                let preview = self.history_preview.and_then(|i| versions.get(i));
+                if ui.add_enabled(preview.is_some(), egui::Button::new("Restore")).clicked() {
+                    restore = preview.map(|v| v.body.clone());
+                }
+                if ui.button("Close").clicked() {
+                    close = true;
+                }
+            });
+        });
 
-                                if note.editing {
-                                    ui.horizontal(|ui| {
-                                        ui.label("Title:");
-                                        if ui.text_edit_singleline(&mut note.title).changed() {
-                                            note.modified = current_unix();
-                                            if self.settings.auto_save {
-                                                self.dirty = true;
-                                            }
-                                        }
-                                    });
-                                } else {
-                                    ui.horizontal(|ui| {
-                                        ui.label("");
-                                        ui.label(egui::RichText::new(&note.title).heading());
-                                    });
-                                }
+        if let Some(body) = restore {
+            let note = &mut self.notes[note_idx];
+            note.body = body;
+            note.modified = current_unix();
+            self.dirty = true;
+            close = true;
+        }
 
-                                ui.separator();
+        if close || modal.should_close() {
+            self.history_note = None;
+            self.history_preview = None;
+        }
+    }
 
-                                if note.editing {
-                                    ui.label("Body:");
-                                    let available_height = ui.available_height();
-                                    egui::ScrollArea::vertical()
-                                        .max_height(available_height * 0.7)
-                                        .show(ui, |ui| {
-                                            if ui
-                                                .add(egui::TextEdit::multiline(&mut note.body)
-                                                    .desired_rows(0)
-                                                    .desired_width(450.0))
-                                                .changed()
-                                            {
-                                                note.modified = current_unix();
-                                                if self.settings.auto_save {
-                                                    self.dirty = true;
-                                                }
-                                            }
-                                        });
-                                } else {
-                                    let available_height = ui.available_height();
-                                    egui::ScrollArea::vertical()
-                                        .max_height(available_height * 0.7)
-                                        .show(ui, |ui| {
-                                            ui.label(&note.body);
-                                        });
-                                }
+    fn show_find_replace_window(&mut self, ctx: &egui::Context) {
+        let Some(state) = &mut self.find_replace else {
+            return;
+        };
+
+        let matches: Vec<(usize, usize)> = if state.find.is_empty() {
+            Vec::new()
+        } else {
+            self.notes
+                .iter()
+                .enumerate()
+                .map(|(i, n)| (i, find_matches(&n.body, &state.find, state.case_sensitive).len()))
+                .filter(|&(_, count)| count > 0)
+                .collect()
+        };
+        let included: Vec<usize> = matches
+            .iter()
+            .filter(|&&(i, _)| {
+                let note = &self.notes[i];
+                !note.editing && !note.locked && !state.excluded.contains(&note.id)
+            })
+            .map(|&(i, _)| i)
+            .collect();
+        let total: usize = matches.iter().map(|&(_, count)| count).sum();
+
+        let mut close = false;
+        let mut apply = false;
+
+        let modal = egui::Modal::new(egui::Id::new("find_replace_modal")).show(ctx, |ui| {
+            ui.set_width(500.0);
+            ui.heading("Find and replace");
+            ui.separator();
+
+            ui.add_enabled_ui(!state.confirming, |ui| {
+                egui::Grid::new("find_replace_fields").num_columns(2).show(ui, |ui| {
+                    ui.label("Find:");
+                    ui.add(egui::TextEdit::singleline(&mut state.find).desired_width(f32::INFINITY));
+                    ui.end_row();
+                    ui.label("Replace with:");
+                    ui.add(egui::TextEdit::singleline(&mut state.replace).desired_width(f32::INFINITY));
+                    ui.end_row();
+                });
+                ui.checkbox(&mut state.case_sensitive, "Case sensitive");
+            });
+            ui.separator();
+
+            if !state.find.is_empty() {
+                ui.label(format!("{} matches in {} notes", total, matches.len()));
+            }
+            egui::ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+                for &(i, count) in &matches {
+                    let note = &self.notes[i];
+                    ui.horizontal(|ui| {
+                        let mut include = !state.excluded.contains(&note.id) && !note.locked;
+                        let checkbox = ui.add_enabled(
+                            !note.editing && !note.locked && !state.confirming,
+                            egui::Checkbox::new(&mut include, format!("{} ({})", note.title, count)),
+                        );
+                        let reason = if note.locked {
+                            "Unlock this note to include it"
+                        } else {
+                            "Finish editing this note to include it"
+                        };
+                        if checkbox.on_disabled_hover_text(reason).changed() {
+                            if include {
+                                state.excluded.remove(&note.id);
+                            } else {
+                                state.excluded.insert(note.id);
+                            }
+                        }
+                    });
+                    let preview = match_preview(&note.body, &state.find, state.case_sensitive);
+                    ui.label(egui::RichText::new(preview).size(10.0).weak());
+                }
+            });
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if state.confirming {
+                    ui.label(format!("Replace in {} notes? This cannot be undone.", included.len()));
+                    if ui.button("Replace").clicked() {
+                        apply = true;
+                    }
+                    if ui.button("Back").clicked() {
+                        state.confirming = false;
+                    }
+                } else {
+                    let label = format!("Replace in {} notes…", included.len());
+                    if ui.add_enabled(!included.is_empty(), egui::Button::new(label)).clicked() {
+                        state.confirming = true;
+                    }
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                }
+            });
+        });
+
+        if apply {
+            for &i in &included {
+                let note = &mut self.notes[i];
+                note.body = replace_matches(&note.body, &state.find, &state.replace, state.case_sensitive);
+                note.modified = current_unix();
+            }
+            self.dirty = true;
+            close = true;
+        }
+
+        if close || modal.should_close() {
+            self.find_replace = None;
+        }
+    }
+
+    fn open_export_dialog(&mut self, note_id: u128) {
+        let Some(note) = self.notes.iter().find(|n| n.id == note_id) else {
+            return;
+        };
+        let format = ExportFormat::Markdown;
+        let mut path = dirs::document_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| std::path::PathBuf::from("."));
+        path.push(export_file_name(&self.settings.export_name_template, note));
+        path.set_extension(format.extension());
+        self.export_dialog = Some(ExportDialog {
+            note_id,
+            format,
+            path: path.to_string_lossy().to_string(),
+            error: None,
+        });
+    }
+
+    fn show_export_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.export_dialog else {
+            return;
+        };
+        let Some(note) = self.notes.iter().find(|n| n.id == dialog.note_id) else {
+            self.export_dialog = None;
+            return;
+        };
+
+        let mut close = false;
+        let mut export = false;
+
+        let modal = egui::Modal::new(egui::Id::new("export_modal")).show(ctx, |ui| {
+            ui.set_width(450.0);
+            ui.heading(format!("Export {}", note.title));
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Format:");
+                for format in ExportFormat::ALL {
+                    if ui.selectable_label(dialog.format == format, format.label()).clicked() {
+                        dialog.format = format;
+                        let mut path = std::path::PathBuf::from(&dialog.path);
+                        path.set_extension(format.extension());
+                        dialog.path = path.to_string_lossy().to_string();
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("File name:");
+                let response = ui
+                    .add(egui::TextEdit::singleline(&mut self.settings.export_name_template).desired_width(200.0))
+                    .on_hover_text("{title}, {date} and {id} are replaced; the result is made safe for file names");
+                if response.changed() {
+                    let mut path = std::path::PathBuf::from(&dialog.path);
+                    path.set_file_name(export_file_name(&self.settings.export_name_template, note));
+                    path.set_extension(dialog.format.extension());
+                    dialog.path = path.to_string_lossy().to_string();
+                    self.settings_changed = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Save to:");
+                ui.add(egui::TextEdit::singleline(&mut dialog.path).desired_width(f32::INFINITY));
+            });
+
+            let mut export_front_matter = self.settings.export_front_matter;
+            if ui
+                .add_enabled(
+                    !matches!(dialog.format, ExportFormat::Pdf | ExportFormat::Bundle),
+                    egui::Checkbox::new(&mut export_front_matter, "Include front matter (title, dates, tags)"),
+                )
+                .changed()
+            {
+                self.settings.export_front_matter = export_front_matter;
+                self.settings_changed = true;
+            }
+
+            if let Some(error) = &dialog.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                export = ui.add_enabled(!dialog.path.trim().is_empty(), egui::Button::new("Export")).clicked();
+                close = ui.button("Cancel").clicked();
+            });
+        });
+
+        if export {
+            let contents = match dialog.format {
+                ExportFormat::Bundle => export_bundle(note, &self.attachments_dir),
+                format => Ok(export_note(note, format, self.settings.export_front_matter)),
+            };
+            match contents.and_then(|contents| fs::write(dialog.path.trim(), contents)) {
+                Ok(()) => close = true,
+                Err(e) => dialog.error = Some(format!("Failed to export: {}", e)),
+            }
+        }
+
+        if close || modal.should_close() {
+            self.export_dialog = None;
+        }
+    }
+
+    fn attach_file(&mut self, idx: usize, source: &std::path::Path) {
+        if self.read_only {
+            self.attach_error = Some("Notes is open read-only, so files can't be attached".to_owned());
+            return;
+        }
+        let Some(note) = self.notes.get_mut(idx) else {
+            return;
+        };
+        match copy_attachment(&self.attachments_dir, note.id, source) {
+            Ok(relative) => {
+                note.attachments.push(relative);
+                note.modified = current_unix();
+                self.attach_input.clear();
+                self.attach_error = None;
+                self.dirty = true;
+            }
+            Err(e) => self.attach_error = Some(format!("Failed to attach {}: {}", source.display(), e)),
+        }
+    }
+
+    fn remove_attachment(&mut self, idx: usize, attachment: usize) {
+        let Some(note) = self.notes.get_mut(idx) else {
+            return;
+        };
+        if attachment >= note.attachments.len() {
+            return;
+        }
+        let relative = note.attachments.remove(attachment);
+        if self.read_only {
+            // The other instance still has the note with this attachment.
+        } else if let Err(e) = fs::remove_file(self.attachments_dir.join(&relative)) {
+            eprintln!("Failed to remove attachment {}: {}", relative, e);
+        }
+        note.modified = current_unix();
+        self.dirty = true;
+    }
+
+    fn show_orphaned_attachments_window(&mut self, ctx: &egui::Context) {
+        if self.read_only {
+            self.orphaned_attachments.clear();
+        }
+        if self.orphaned_attachments.is_empty() {
+            return;
+        }
+
+        let mut delete = false;
+        let mut keep = false;
+
+        let modal = egui::Modal::new(egui::Id::new("orphaned_attachments_modal")).show(ctx, |ui| {
+            ui.set_width(350.0);
+            ui.heading("Remove attachments?");
+            ui.separator();
+            let count = self.orphaned_attachments.len();
+            ui.label(format!(
+                "The deleted notes had {} attached file{}. Delete {} from the attachments folder too?",
+                count,
+                if count == 1 { "" } else { "s" },
+                if count == 1 { "it" } else { "them" },
+            ));
+            for relative in self.orphaned_attachments.iter().take(10) {
+                ui.label(egui::RichText::new(attachment_name(relative)).size(10.0).weak());
+            }
+            if count > 10 {
+                ui.label(egui::RichText::new(format!("…and {} more", count - 10)).size(10.0).weak());
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                delete = ui.button("Delete files").clicked();
+                keep = ui.button("Keep").clicked();
+            });
+        });
+
+        if delete {
+            for relative in self.orphaned_attachments.drain(..) {
+                let path = self.attachments_dir.join(&relative);
+                if let Err(e) = fs::remove_file(&path) {
+                    eprintln!("Failed to remove attachment {}: {}", relative, e);
+                }
+                if let Some(folder) = path.parent() {
+                    let _ = fs::remove_dir(folder);
+                }
+            }
+        }
+        if keep || modal.should_close() {
+            self.orphaned_attachments.clear();
+        }
+    }
+
+    fn show_data_dir_prompt(&mut self, ctx: &egui::Context) {
+        let Some(problem) = &mut self.data_dir_problem else {
+            return;
+        };
+
+        let mut use_folder = false;
+        let mut dismiss = false;
+
+        egui::Modal::new(egui::Id::new("data_dir_modal")).show(ctx, |ui| {
+            ui.set_width(450.0);
+            ui.heading("Notes can't be saved");
+            ui.separator();
+            ui.colored_label(ui.visuals().error_fg_color, &problem.error);
+            ui.label("Choose another folder to keep your notes, settings and history in. It will be remembered for next time.");
+            ui.horizontal(|ui| {
+                ui.label("Folder:");
+                ui.add(egui::TextEdit::singleline(&mut problem.alternative).desired_width(f32::INFINITY));
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                use_folder = ui
+                    .add_enabled(!problem.alternative.trim().is_empty(), egui::Button::new("Use this folder"))
+                    .clicked();
+                dismiss = ui
+                    .button("Continue without saving")
+                    .on_hover_text("Changes will be lost when the app closes")
+                    .clicked();
+            });
+        });
+
+        if use_folder {
+            let dir = std::path::PathBuf::from(problem.alternative.trim());
+            let result = check_writable(&dir)
+                .map_err(|e| format!("{} can't be written to: {}", dir.display(), e))
+                .and_then(|()| save_app_dir(&dir).map_err(|e| format!("Failed to remember the folder: {}", e)));
+            match result {
+                Ok(()) => {
+                    self.instance_lock = None;
+                    *self = NotesApp::default();
+                    self.apply_theme(ctx);
+                    self.apply_font_settings(ctx);
+                    ctx.set_zoom_factor(self.settings.ui_scale);
+                }
+                Err(error) => problem.error = error,
+            }
+        }
+        if dismiss {
+            self.data_dir_problem = None;
+        }
+    }
+
+    fn show_lock_prompt(&mut self, ctx: &egui::Context) {
+        let Some(pid) = self.lock_holder else {
+            return;
+        };
+        let other = match pid {
+            Some(pid) => format!("Another instance (process {})", pid),
+            None => "Another instance".to_owned(),
+        };
+
+        let mut read_only = false;
+        let mut take_over = false;
+        let mut quit = false;
+
+        egui::Modal::new(egui::Id::new("instance_lock_modal")).show(ctx, |ui| {
+            ui.set_width(380.0);
+            ui.heading("Notes is already open");
+            ui.separator();
+            ui.label(format!("{} is using your notes. If both save, one will overwrite the other's changes.", other));
+            ui.add_space(4.0);
+            ui.label(egui::RichText::new("Open anyway only if that instance has crashed or is stuck.").size(10.0).weak());
+            ui.separator();
+            ui.horizontal(|ui| {
+                read_only = ui.button("Open read-only").clicked();
+                take_over = ui.button("Open anyway").clicked();
+                quit = ui.button("Quit").clicked();
+            });
+        });
+
+        if read_only {
+            self.lock_holder = None;
+        }
+        if take_over {
+            self.instance_lock = Some(InstanceLock::take_over(self.store.dir.join(".lock")));
+            self.lock_holder = None;
+            self.read_only = false;
+        }
+        if quit {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+
+    fn prunable_notes(&self) -> Vec<u128> {
+        self.notes
+            .iter()
+            .filter(|n| is_default_title(&n.title) && n.body.trim().is_empty() && !n.editing)
+            .filter(|n| n.modified == n.created && n.tags.is_empty() && n.attachments.is_empty())
+            .filter(|n| !n.pinned && n.pinned_in.is_empty() && n.icon.is_none() && !n.locked)
+            .map(|n| n.id)
+            .collect()
+    }
+
+    fn prune_untitled_notes(&mut self) {
+        let prunable = self.prunable_notes();
+        while let Some(idx) = self.notes.iter().position(|n| prunable.contains(&n.id)) {
+            self.delete_note(idx);
+        }
+        self.save_notes();
+    }
+
+    fn prune_on_close(&mut self, ctx: &egui::Context) {
+        if !self.settings.prune_untitled || self.read_only || self.prune_skipped {
+            return;
+        }
+        if !ctx.input(|i| i.viewport().close_requested()) {
+            return;
+        }
+        let count = self.prunable_notes().len();
+        if count == 0 {
+            return;
+        }
+        if self.settings.prune_untitled_confirmed {
+            self.prune_untitled_notes();
+        } else {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.prune_prompt = Some(count);
+        }
+    }
+
+    fn show_prune_prompt(&mut self, ctx: &egui::Context) {
+        let Some(count) = self.prune_prompt else {
+            return;
+        };
+
+        let mut remove = false;
+        let mut keep = false;
+        let mut cancel = false;
+
+        let modal = egui::Modal::new(egui::Id::new("prune_untitled_modal")).show(ctx, |ui| {
+            ui.set_width(380.0);
+            ui.heading("Remove empty notes?");
+            ui.separator();
+            ui.label(format!(
+                "{} untitled {} never written in will be removed. Notes you've edited are always kept.",
+                count,
+                if count == 1 { "note was" } else { "notes were" }
+            ));
+            ui.add_space(4.0);
+            ui.label(egui::RichText::new("You won't be asked again. This can be turned off in Settings.").size(10.0).weak());
+            ui.separator();
+            ui.horizontal(|ui| {
+                remove = ui.button("Remove and quit").clicked();
+                keep = ui.button("Keep and quit").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+
+        if remove {
+            self.settings.prune_untitled_confirmed = true;
+            self.settings_changed = true;
+            self.prune_untitled_notes();
+        }
+        if keep {
+            self.prune_skipped = true;
+        }
+        if remove || keep {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+        if remove || keep || cancel || modal.should_close() {
+            self.prune_prompt = None;
+        }
+    }
+
+    fn show_locked_delete_prompt(&mut self, ctx: &egui::Context) {
+        let Some(id) = self.locked_delete else {
+            return;
+        };
+        let Some(idx) = self.notes.iter().position(|n| n.id == id) else {
+            self.locked_delete = None;
+            return;
+        };
+
+        let mut delete = false;
+        let mut cancel = false;
+
+        let modal = egui::Modal::new(egui::Id::new("locked_delete_modal")).show(ctx, |ui| {
+            ui.set_width(380.0);
+            ui.heading("Delete a locked note?");
+            ui.separator();
+            ui.label(format!("\"{}\" is locked to protect it from changes.", self.notes[idx].title));
+            ui.colored_label(ui.visuals().error_fg_color, "Deleting it also removes its history and can't be undone.");
+            ui.separator();
+            ui.horizontal(|ui| {
+                cancel = ui.button("Keep note").clicked();
+                delete = ui
+                    .button(egui::RichText::new("Delete locked note").color(ui.visuals().error_fg_color))
+                    .clicked();
+            });
+        });
+
+        if delete {
+            self.remove_note(idx);
+        }
+        if delete || cancel || modal.should_close() {
+            self.locked_delete = None;
+        }
+    }
+
+    fn has_unsaved_edits(&self) -> bool {
+        !self.settings.auto_save
+            && self
+                .selected
+                .and_then(|idx| self.notes.get(idx))
+                .is_some_and(|n| n.editing && n.backup.as_ref() != Some(&n.body))
+    }
+
+    // `dirty` only says something may have changed; the notes are compared with what was
+    // last saved so that changes which put things back, or aren't edits, don't count.
+    fn has_pending_changes(&self) -> bool {
+        self.notes.iter().any(|n| n.editing && n.backup.as_ref() != Some(&n.body))
+            || (self.dirty && self.store.differs_from(&self.notes))
+    }
+
+    fn discard_all_changes(&mut self) -> Result<(), String> {
+        let selected_id = self.selected.and_then(|idx| self.notes.get(idx)).map(|n| n.id);
+        let notes = self.store.load_or_migrate(get_data_path(&self.app_dir)).map_err(|e| e.to_string())?;
+        self.notes = notes;
+        self.selected = selected_id
+            .and_then(|id| self.notes.iter().position(|n| n.id == id))
+            .or(if self.notes.is_empty() { None } else { Some(0) });
+        self.dirty = false;
+        self.dirty_since = None;
+        self.save_failure = None;
+        self.load_problems = self.store.skipped_files();
+        Ok(())
+    }
+
+    fn show_discard_all_prompt(&mut self, ctx: &egui::Context) {
+        if !self.discard_all_prompt {
+            return;
+        }
+
+        let mut discard = false;
+        let mut cancel = false;
+
+        let modal = egui::Modal::new(egui::Id::new("discard_all_modal")).show(ctx, |ui| {
+            ui.set_width(350.0);
+            ui.heading("Discard all changes?");
+            ui.separator();
+            ui.label("Every note is reloaded from disk. Unsaved edits, new notes and deletions since the last save are lost.");
+            if let Some(error) = &self.discard_all_error {
+                ui.label(egui::RichText::new(format!("Couldn't reload notes: {}", error)).size(10.0).color(ui.visuals().error_fg_color));
+            }
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                discard = ui
+                    .button(egui::RichText::new("Discard all").color(ui.visuals().error_fg_color))
+                    .clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+
+        if discard {
+            self.discard_all_error = self.discard_all_changes().err();
+        }
+        if (discard && self.discard_all_error.is_none()) || cancel || modal.should_close() {
+            self.discard_all_prompt = false;
+            self.discard_all_error = None;
+        }
+    }
+
+    fn save_selected_edit(&mut self) {
+        let Some(note) = self.selected.and_then(|idx| self.notes.get_mut(idx)).filter(|n| n.editing) else {
+            return;
+        };
+        note.modified = current_unix();
+        note.editing = false;
+        note.backup = None;
+        self.dirty = true;
+        self.save_notes();
+    }
+
+    fn save_and_close(&mut self, ctx: &egui::Context) {
+        self.save_selected_edit();
+        self.focus_region(ctx, FocusRegion::List);
+    }
+
+    fn save_and_new(&mut self, ctx: &egui::Context) {
+        self.save_selected_edit();
+        self.add_note();
+        self.focus_region(ctx, FocusRegion::Editor);
+    }
+
+    // Returns false when the switch has to wait for the unsaved-changes prompt.
+    fn prepare_switch(&mut self, target: usize) -> bool {
+        if Some(target) == self.selected || !self.has_unsaved_edits() {
+            return true;
+        }
+        if self.settings.save_on_switch {
+            self.save_selected_edit();
+            return true;
+        }
+        if self.settings.confirm_switch_while_editing {
+            match self.notes.get(target) {
+                Some(note) => self.pending_switch = Some(note.id),
+                None => self.pending_capture = true,
+            }
+            return false;
+        }
+        true
+    }
+
+    fn show_switch_prompt(&mut self, ctx: &egui::Context) {
+        if self.pending_switch.is_none() && !self.pending_capture {
+            return;
+        }
+
+        let mut save = false;
+        let mut discard = false;
+        let mut cancel = false;
+
+        let modal = egui::Modal::new(egui::Id::new("switch_prompt_modal")).show(ctx, |ui| {
+            ui.set_width(350.0);
+            ui.heading("Unsaved changes");
+            ui.separator();
+            let title = self.selected.and_then(|idx| self.notes.get(idx)).map(|n| n.title.as_str()).unwrap_or_default();
+            ui.label(format!("\"{}\" has changes that haven't been saved.", title));
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                save = ui.button("Save").clicked();
+                discard = ui.button("Discard").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+
+        if save {
+            self.save_selected_edit();
+        }
+        if discard {
+            if let Some(note) = self.selected.and_then(|idx| self.notes.get_mut(idx)) {
+                if let Some(original) = note.backup.take() {
+                    note.body = original;
+                }
+                note.editing = false;
+            }
+        }
+        if save || discard {
+            match self.pending_switch {
+                Some(target_id) => {
+                    if let Some(idx) = self.notes.iter().position(|n| n.id == target_id) {
+                        self.pick_note(idx);
+                    }
+                }
+                None => {
+                    self.add_note();
+                    self.focus_region(ctx, FocusRegion::Editor);
+                }
+            }
+        }
+
+        if save || discard || cancel || modal.should_close() {
+            self.pending_switch = None;
+            self.pending_capture = false;
+        }
+    }
+
+    fn show_paste_json_window(&mut self, ctx: &egui::Context) {
+        let Some(json) = &mut self.paste_json else {
+            return;
+        };
+
+        let mut close = false;
+        let mut import = false;
+
+        let modal = egui::Modal::new(egui::Id::new("paste_json_modal")).show(ctx, |ui| {
+            ui.set_width(400.0);
+            ui.heading("Paste note from JSON");
+            ui.separator();
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(json)
+                        .code_editor()
+                        .hint_text("{ \"title\": \"...\", \"body\": \"...\" }")
+                        .desired_width(f32::INFINITY)
+                        .desired_rows(10),
+                );
+            });
+            if let Some(error) = &self.paste_json_error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                import = ui.add_enabled(!json.trim().is_empty(), egui::Button::new("Import")).clicked();
+                close = ui.button("Cancel").clicked();
+            });
+        });
+
+        if import {
+            match serde_json::from_str::<SharedNote>(json) {
+                Ok(shared) => {
+                    let mut note = Note::new(rand::random::<u128>());
+                    if !shared.title.trim().is_empty() {
+                        note.title = shared.title;
+                    }
+                    note.body = normalize_line_endings(&shared.body);
+                    note.tags = shared.tags;
+                    self.notes.insert(0, note);
+                    self.selected = Some(0);
+                    self.dirty = true;
+                    close = true;
+                }
+                Err(e) => self.paste_json_error = Some(format!("Not a valid note: {}", e)),
+            }
+        }
+
+        if close || modal.should_close() {
+            self.paste_json = None;
+            self.paste_json_error = None;
+        }
+    }
+
+    fn auto_save(&mut self, ctx: &egui::Context) {
+        let (now, focused, close_requested) = ctx.input(|i| {
+            (i.time, i.viewport().focused.unwrap_or(true), i.viewport().close_requested())
+        });
+        let lost_focus = self.window_focused && !focused;
+        self.window_focused = focused;
+
+        if !self.dirty {
+            self.dirty_since = None;
+            return;
+        }
+        if !self.settings.auto_save {
+            return;
+        }
+        if let Some(failure) = &self.save_failure {
+            let wait = failure.retry_at.saturating_duration_since(std::time::Instant::now());
+            if !wait.is_zero() && !close_requested {
+                ctx.request_repaint_after(wait);
+                return;
+            }
+        }
+
+        let dirty_since = *self.dirty_since.get_or_insert(now);
+        let elapsed = now - dirty_since;
+        if elapsed >= AUTO_SAVE_DELAY || lost_focus || close_requested {
+            self.save_notes();
+            self.dirty_since = None;
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(AUTO_SAVE_DELAY - elapsed));
+        }
+    }
+
+    fn expire_notes(&mut self, ctx: &egui::Context) {
+        let now = current_unix();
+        let mut next: Option<u64> = None;
+        for note in self.notes.iter_mut().filter(|n| !n.archived) {
+            match note.expires_at {
+                Some(expires_at) if expires_at > now => next = Some(next.map_or(expires_at, |n| n.min(expires_at))),
+                Some(_) if !note.editing => {
+                    note.archived = true;
+                    note.pinned = false;
+                    note.pinned_in.clear();
+                    self.dirty = true;
+                }
+                _ => {}
+            }
+        }
+        if let Some(next) = next {
+            ctx.request_repaint_after(std::time::Duration::from_secs(next - now));
+        }
+    }
+
+    fn track_session_words(&mut self) {
+        let Some(note) = self.selected.and_then(|idx| self.notes.get(idx)).filter(|n| n.editing) else {
+            self.session_word_baseline = None;
+            return;
+        };
+        let words = Self::get_word_count(&note.body, self.settings.word_count_mode);
+        if let Some((id, previous)) = self.session_word_baseline {
+            if id == note.id {
+                self.session_words_written += words.saturating_sub(previous);
+            }
+        }
+        self.session_word_baseline = Some((note.id, words));
+    }
+
+    fn tick_pomodoro(&mut self, ctx: &egui::Context) {
+        let Some(pomodoro) = &mut self.pomodoro else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        if now < pomodoro.ends {
+            ctx.request_repaint_after((pomodoro.ends - now).min(std::time::Duration::from_secs(1)));
+            return;
+        }
+
+        let message = match pomodoro.phase {
+            PomodoroPhase::Work => {
+                let words = self.session_words_written.saturating_sub(pomodoro.words_at_start);
+                let minutes = self.settings.pomodoro_break_minutes as u64;
+                let message = format!("Writing session over: {} words. Take a {} minute break.", words, minutes);
+                self.pomodoro_result = Some(format!("{} words last session", words));
+                pomodoro.phase = PomodoroPhase::Break;
+                pomodoro.ends = now + std::time::Duration::from_secs(minutes * 60);
+                message
+            }
+            PomodoroPhase::Break => {
+                self.pomodoro = None;
+                "Break over, ready for another session?".to_owned()
+            }
+        };
+        ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(egui::UserAttentionType::Informational));
+        if self.settings.pomodoro_notify {
+            if let Err(e) = notify_desktop("Notes", &message) {
+                eprintln!("Failed to show notification: {}", e);
+            }
+        }
+    }
+
+    // Only a note the user picks counts as opened, not one selected for them on launch,
+    // after a delete or when it's created.
+    fn pick_note(&mut self, idx: usize) {
+        if self.selected != Some(idx) {
+            if let Some(note) = self.notes.get_mut(idx) {
+                note.last_opened = current_unix();
+                self.last_opened_dirty = true;
+            }
+        }
+        self.selected = Some(idx);
+    }
+
+    // Opening a note isn't a change to it, so this is written whatever the auto-save
+    // setting and without saving anything else.
+    fn save_last_opened(&mut self) {
+        if !self.last_opened_dirty || self.read_only {
+            return;
+        }
+        self.last_opened_dirty = false;
+        if let Err(e) = self.store.save_last_opened(&self.notes) {
+            eprintln!("Failed to save when notes were opened: {}", e);
+        }
+    }
+
+    fn show_save_failure_banner(&mut self, ctx: &egui::Context) {
+        let Some(failure) = &self.save_failure else {
+            return;
+        };
+        let mut retry = false;
+        let error_color = ctx.style().visuals.error_fg_color;
+        egui::TopBottomPanel::top("save_failure_banner")
+            .frame(egui::Frame::default()
+                .fill(error_color.gamma_multiply(0.15))
+                .inner_margin(egui::Margin { top: 6, bottom: 6, left: 10, right: 10 })
+            )
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(error_color, format!("⚠ Couldn't save notes: {}", failure.error));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        retry = ui.button("Retry now").clicked();
+                        let status = if self.settings.auto_save {
+                            let wait = failure.retry_at.saturating_duration_since(std::time::Instant::now());
+                            format!("Retrying in {}s", wait.as_secs_f64().ceil())
+                        } else {
+                            "Auto-save is off".to_owned()
+                        };
+                        ui.label(egui::RichText::new(status).size(10.0).weak())
+                            .on_hover_text(format!("{} failed attempts", failure.attempts));
+                    });
+                });
+            });
+        if self.settings.auto_save {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+        if retry {
+            self.save_notes();
+        }
+    }
+
+    fn show_load_problems_banner(&mut self, ctx: &egui::Context) {
+        if self.load_problems.is_empty() {
+            return;
+        }
+        let mut dismiss = false;
+        let warn_color = ctx.style().visuals.warn_fg_color;
+        egui::TopBottomPanel::top("load_problems_banner")
+            .frame(egui::Frame::default()
+                .fill(warn_color.gamma_multiply(0.15))
+                .inner_margin(egui::Margin { top: 6, bottom: 6, left: 10, right: 10 })
+            )
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let summary = match self.load_problems.len() {
+                        1 => "⚠ 1 note file couldn't be loaded and was left as is".to_owned(),
+                        count => format!("⚠ {} note files couldn't be loaded and were left as is", count),
+                    };
+                    ui.colored_label(warn_color, summary)
+                        .on_hover_text(self.load_problems.join("\n"));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        dismiss = ui.button("Dismiss").clicked();
+                        if ui.button("Show folder").clicked() {
+                            if let Err(e) = open_path(&self.store.dir) {
+                                eprintln!("Failed to open {}: {}", self.store.dir.display(), e);
+                            }
+                        }
+                    });
+                });
+            });
+        if dismiss {
+            self.load_problems.clear();
+        }
+    }
+
+    fn show_saved_indicator(&mut self, ui: &mut egui::Ui) {
+        let Some(saved) = self.last_saved else {
+            return;
+        };
+        let elapsed = saved.elapsed().as_secs_f32();
+        if elapsed >= SAVED_INDICATOR_DURATION {
+            self.last_saved = None;
+            return;
+        }
+        let color = ui.visuals().weak_text_color().gamma_multiply(1.0 - elapsed / SAVED_INDICATOR_DURATION);
+        ui.label(egui::RichText::new("Saved ✓").size(10.0).color(color));
+        ui.ctx().request_repaint();
+    }
+
+    fn auto_save_settings(&mut self, ctx: &egui::Context) {
+        let in_settings = self.current_view == AppView::Settings;
+        let left_settings = self.was_in_settings && !in_settings;
+        self.was_in_settings = in_settings;
+
+        if !self.settings_changed {
+            self.settings_changed_since = None;
+            return;
+        }
+
+        let (now, interacting, close_requested) =
+            ctx.input(|i| (i.time, i.pointer.any_down(), i.viewport().close_requested()));
+        let since = self.settings_changed_since.get_or_insert(now);
+        if interacting {
+            *since = now;
+        }
+        let elapsed = now - *since;
+
+        if elapsed >= SETTINGS_SAVE_DELAY || left_settings || close_requested {
+            self.save_settings();
+            self.settings_changed_since = None;
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(SETTINGS_SAVE_DELAY - elapsed));
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = save_settings(&self.settings_path, &self.settings) {
+            eprintln!("Failed to save settings: {}", e);
+        } else {
+            self.settings_changed = false;
+        }
+    }
+
+    fn save_session(&mut self) {
+        if self.read_only {
+            return;
+        }
+        self.session.search = self.settings.remember_search.then(|| SavedSearchState {
+            query: self.search.clone(),
+            scope: self.search_scope,
+            filter: self.filter_preset,
+        });
+        if let Err(e) = save_session(&self.session_path, &self.session) {
+            eprintln!("Failed to save session: {}", e);
+        }
+    }
+
+    fn track_window_geometry(&mut self, ctx: &egui::Context) {
+        let (outer_rect, inner_rect, pixels_per_point, minimized, close_requested) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.outer_rect,
+                viewport.inner_rect,
+                viewport.native_pixels_per_point.unwrap_or(1.0),
+                viewport.minimized.unwrap_or(false),
+                viewport.close_requested(),
+            )
+        });
+
+        if let (Some(outer_rect), Some(inner_rect)) = (outer_rect, inner_rect) {
+            if !self.window_checked {
+                self.window_checked = true;
+
+                // Positions are desktop coordinates, so a secondary monitor can sit at a negative
+                // or large offset; only pull the window back when no monitor shows enough of it.
+                let monitors = monitor_rects().unwrap_or_default();
+                let monitors: Vec<egui::Rect> = monitors.iter().map(|r| *r / pixels_per_point).collect();
+                let visible = |m: &egui::Rect| {
+                    let shown = m.intersect(outer_rect);
+                    shown.width() >= MIN_VISIBLE_WINDOW.x && shown.height() >= MIN_VISIBLE_WINDOW.y
+                };
+                if self.session.window.is_some() && !monitors.is_empty() && !monitors.iter().any(visible) {
+                    let primary = monitors.iter().find(|m| m.contains(egui::Pos2::ZERO)).unwrap_or(&monitors[0]);
+                    let size = inner_rect.size().min(primary.size());
+                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(primary.min));
+                }
+            }
+
+            if !minimized {
+                self.session.window = Some(WindowGeometry {
+                    x: outer_rect.min.x,
+                    y: outer_rect.min.y,
+                    width: inner_rect.width(),
+                    height: inner_rect.height(),
+                });
+            }
+        }
+
+        if close_requested {
+            self.save_session();
+        }
+    }
+
+    fn apply_theme(&self, ctx: &egui::Context) {
+        let preference = match self.settings.theme {
+            ThemeMode::Dark | ThemeMode::HighContrast => egui::ThemePreference::Dark,
+            ThemeMode::Light => egui::ThemePreference::Light,
+            ThemeMode::System => egui::ThemePreference::System,
+        };
+        let dark_visuals = match self.settings.theme {
+            ThemeMode::HighContrast => high_contrast_visuals(),
+            _ => egui::Visuals::dark(),
+        };
+        ctx.set_visuals_of(egui::Theme::Dark, dark_visuals);
+        ctx.set_theme(preference);
+    }
+
+    fn apply_font_settings(&self, ctx: &egui::Context) {
+        ctx.all_styles_mut(|style| {
+            style.text_styles.get_mut(&egui::TextStyle::Body).unwrap().size = self.settings.font_size;
+            style.text_styles.get_mut(&egui::TextStyle::Heading).unwrap().size = self.settings.font_size + 7.0;
+            style.text_styles.get_mut(&egui::TextStyle::Button).unwrap().size = self.settings.font_size - 2.0;
+        });
+    }
+
+    fn focused_region(&self, ctx: &egui::Context) -> FocusRegion {
+        let focused = ctx.memory(|m| m.focused());
+        let body_id = self.selected.and_then(|idx| self.notes.get(idx)).map(|n| egui::Id::new("note_body").with(n.id));
+        if focused == Some(egui::Id::new("note_search")) {
+            FocusRegion::Search
+        } else if focused.is_some() && focused == body_id {
+            FocusRegion::Editor
+        } else {
+            FocusRegion::List
+        }
+    }
+
+    fn focus_region(&mut self, ctx: &egui::Context, region: FocusRegion) {
+        match region {
+            FocusRegion::Search => ctx.memory_mut(|m| m.request_focus(egui::Id::new("note_search"))),
+            FocusRegion::List => {
+                if let Some(id) = ctx.memory(|m| m.focused()) {
+                    ctx.memory_mut(|m| m.surrender_focus(id));
+                }
+                self.focus_list = true;
+            }
+            FocusRegion::Editor => {
+                let Some(note) = self.selected.and_then(|idx| self.notes.get_mut(idx)) else {
+                    return self.focus_region(ctx, FocusRegion::Search);
+                };
+                if note.start_editing() {
+                    ctx.memory_mut(|m| m.request_focus(egui::Id::new("note_body").with(note.id)));
+                }
+            }
+        }
+    }
+
+    fn zoom_font(&mut self, ctx: &egui::Context, delta: f32) {
+        let font_size = (self.settings.font_size + delta).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        if font_size != self.settings.font_size {
+            self.settings.font_size = font_size;
+            self.apply_font_settings(ctx);
+            self.settings_changed = true;
+        }
+    }
+
+    fn dialog_open(&self) -> bool {
+        self.show_shortcuts
+            || self.history_note.is_some()
+            || self.paste_json.is_some()
+            || self.find_replace.is_some()
+            || self.export_dialog.is_some()
+            || self.pending_switch.is_some()
+            || self.pending_capture
+            || !self.orphaned_attachments.is_empty()
+            || self.lock_holder.is_some()
+            || self.command_input.is_some()
+            || self.data_dir_problem.is_some()
+            || self.prune_prompt.is_some()
+            || self.locked_delete.is_some()
+            || self.discard_all_prompt
+    }
+
+    // Escape does one thing per press, in this order; dialogs come first and
+    // close themselves, so nothing here runs while one is open.
+    fn escape_action(&self) -> Option<EscapeAction> {
+        if self.dialog_open() {
+            None
+        } else if self.dragging.is_some() {
+            Some(EscapeAction::CancelDrag)
+        } else if !self.zen_mode && self.selected_note_editing() {
+            Some(EscapeAction::CancelEdit)
+        } else if self.zen_mode {
+            Some(EscapeAction::LeaveZenMode)
+        } else if self.current_view == AppView::Notes && !self.search.is_empty() {
+            Some(EscapeAction::ClearSearch)
+        } else {
+            None
+        }
+    }
+
+    fn handle_escape(&mut self, ctx: &egui::Context) {
+        let Some(action) = self.escape_action().filter(|_| !egui::Popup::is_any_open(ctx)) else {
+            return;
+        };
+        if !ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape)) {
+            return;
+        }
+        match action {
+            EscapeAction::CancelDrag => {
+                self.dragging = None;
+                self.drag_start_pos = None;
+            }
+            EscapeAction::CancelEdit => {
+                if let Some(note) = self.selected.and_then(|idx| self.notes.get_mut(idx)) {
+                    if let Some(original) = note.backup.take() {
+                        note.body = original;
+                    }
+                    note.editing = false;
+                }
+            }
+            EscapeAction::LeaveZenMode => self.zen_mode = false,
+            EscapeAction::ClearSearch => {
+                self.search.clear();
+                self.saved_search_name = None;
+            }
+        }
+    }
+
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        let typing = ctx.wants_keyboard_input();
+        let dialog_open = self.dialog_open();
+        let list_focused = !typing
+            && self.current_view == AppView::Notes
+            && !self.zen_mode
+            && !dialog_open;
+        let (show_shortcuts, find_replace, daily_note, zen_mode, word_count, delete, zoom_in, zoom_out, zoom_delta) = ctx.input_mut(|i| {
+            let show_shortcuts = i.consume_shortcut(&SHOW_SHORTCUTS)
+                || (!typing && i.consume_shortcut(&SHOW_SHORTCUTS_ALT));
+            let find_replace = i.consume_shortcut(&FIND_REPLACE);
+            let daily_note = !dialog_open && i.consume_shortcut(&DAILY_NOTE);
+            let zen_mode = i.consume_shortcut(&ZEN_MODE);
+            let word_count = i.consume_shortcut(&TOGGLE_WORD_COUNT);
+            let delete = list_focused && i.consume_shortcut(&DELETE_NOTE);
+            let zoom_in = i.consume_shortcut(&ZOOM_IN) || i.consume_shortcut(&ZOOM_IN_ALT);
+            let zoom_out = i.consume_shortcut(&ZOOM_OUT);
+            (show_shortcuts, find_replace, daily_note, zen_mode, word_count, delete, zoom_in, zoom_out, i.zoom_delta())
+        });
+
+        if show_shortcuts {
+            self.show_shortcuts = !self.show_shortcuts;
+        }
+
+        let open_command = list_focused
+            && ctx.input_mut(|i| {
+                let count = i.events.len();
+                i.events.retain(|e| !matches!(e, egui::Event::Text(t) if t == ":"));
+                i.events.len() != count
+            });
+        if open_command {
+            self.command_input = Some(String::new());
+            self.command_error = None;
+        }
+
+        let regions_available = self.current_view == AppView::Notes
+            && self.settings.notes_layout == NotesLayout::List
+            && !self.zen_mode
+            && !self.dialog_open();
+        if regions_available {
+            let (previous, next) =
+                ctx.input_mut(|i| (i.consume_shortcut(&PREVIOUS_REGION), i.consume_shortcut(&NEXT_REGION)));
+            if previous || next {
+                let region = self.focused_region(ctx).step(next);
+                self.focus_region(ctx, region);
+            }
+        }
+
+        if let Some(shortcut) = self.quick_capture_shortcut().filter(|_| !self.dialog_open()) {
+            if ctx.input_mut(|i| i.consume_shortcut(&shortcut)) {
+                self.quick_capture(ctx);
+            }
+        }
+
+        if !self.dialog_open() && ctx.input_mut(|i| i.consume_shortcut(&OPEN_SETTINGS)) {
+            self.zen_mode = false;
+            self.current_view = AppView::Settings;
+            self.focus_first_setting = true;
+        }
+
+        if daily_note {
+            self.open_daily_note(ctx);
+        }
+
+        if word_count {
+            self.settings.show_word_count = !self.settings.show_word_count;
+            self.settings_changed = true;
+        }
+
+        let editing = self.current_view == AppView::Notes
+            && !self.zen_mode
+            && !self.dialog_open()
+            && self.selected.and_then(|idx| self.notes.get(idx)).is_some_and(|n| n.editing);
+        if editing {
+            let (save_and_new, save_and_close) =
+                ctx.input_mut(|i| (i.consume_shortcut(&SAVE_AND_NEW), i.consume_shortcut(&SAVE_AND_CLOSE)));
+            if save_and_new {
+                self.save_and_new(ctx);
+            } else if save_and_close {
+                self.save_and_close(ctx);
+            }
+        }
+
+        if find_replace && self.find_replace.is_none() {
+            self.find_replace = Some(FindReplace::default());
+        }
+
+        if delete {
+            if self.multi_select && !self.checked.is_empty() {
+                self.delete_checked();
+            } else {
+                self.delete_selected();
+            }
+        }
+
+        if zen_mode {
+            if self.zen_mode {
+                self.zen_mode = false;
+            } else if self.current_view == AppView::Notes {
+                self.enter_zen_mode();
+            }
+        }
+
+        // A smooth-scroll gesture spreads its zoom over many frames, so step once per
+        // ZOOM_SCROLL_STEP of accumulated zoom instead of once per frame.
+        self.zoom_scroll += zoom_delta.ln();
+        let mut steps = 0.0;
+        while self.zoom_scroll.abs() >= ZOOM_SCROLL_STEP {
+            let step = self.zoom_scroll.signum();
+            self.zoom_scroll -= step * ZOOM_SCROLL_STEP;
+            steps += step;
+        }
+        if zoom_in {
+            steps += 1.0;
+        } else if zoom_out {
+            steps -= 1.0;
+        }
+        if steps != 0.0 {
+            self.zoom_font(ctx, steps);
+        }
+    }
+
+    fn enter_zen_mode(&mut self) {
+        let Some(note) = self.selected.and_then(|idx| self.notes.get_mut(idx)) else {
+            return;
+        };
+        if note.start_editing() {
+            self.zen_mode = true;
+        }
+    }
+
+    fn show_zen_editor(&mut self, ctx: &egui::Context) {
+        let Some(idx) = self.selected.filter(|&idx| idx < self.notes.len()) else {
+            self.zen_mode = false;
+            return;
+        };
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::default()
+                .fill(ctx.style().visuals.panel_fill)
+                .inner_margin(egui::Margin { top: 30, bottom: 30, left: 20, right: 20 })
+                .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+            )
+            .show(ctx, |ui| {
+                let column_width = ui.available_width().min(ZEN_COLUMN_WIDTH);
+                let side_space = (ui.available_width() - column_width) / 2.0;
+
+                ui.horizontal_top(|ui| {
+                    ui.add_space(side_space);
+                    ui.vertical(|ui| {
+                        ui.set_width(column_width);
+                        let note = &mut self.notes[idx];
+                        let body_id = egui::Id::new("note_body").with(note.id);
+
+                        ui.label(egui::RichText::new(&note.title).heading());
+                        ui.label(egui::RichText::new("Press Escape to leave zen mode").size(10.0).weak());
+                        ui.separator();
+
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            let paper = if self.settings.paper_styles { note.paper } else { PaperStyle::Plain };
+                            let paper_bg = ui.painter().add(egui::Shape::Noop);
+                            let output = egui::TextEdit::multiline(&mut note.body)
+                                .id(body_id)
+                                .hint_text(self.settings.body_hint.as_str())
+                                .frame(false)
+                                .desired_width(column_width)
+                                .desired_rows(20)
+                                .show(ui);
+                            let shapes = paper_shapes(ui, paper, output.response.rect, output.galley_pos.y, None);
+                            ui.painter().set(paper_bg, shapes);
+                            if output.response.changed() {
+                                note.modified = current_unix();
+                                if self.settings.auto_save {
+                                    self.dirty = true;
+                                }
+                            }
+                        });
+                    });
+                });
+            });
+    }
+
+    fn show_shortcuts_window(&mut self, ctx: &egui::Context) {
+        let mut close_clicked = false;
+        let modal = egui::Modal::new(egui::Id::new("shortcuts_modal")).show(ctx, |ui| {
+            ui.heading("Keyboard Shortcuts");
+            ui.separator();
+
+            let mut categories: Vec<&str> = Vec::new();
+            for shortcut in SHORTCUTS {
+                if !categories.contains(&shortcut.category) {
+                    categories.push(shortcut.category);
+                }
+            }
+
+            for category in categories {
+                ui.add_space(5.0);
+                ui.label(egui::RichText::new(category).strong());
+                egui::Grid::new(("shortcuts", category)).num_columns(2).spacing([20.0, 4.0]).show(ui, |ui| {
+                    for shortcut in SHORTCUTS.iter().filter(|s| s.category == category) {
+                        let keys: Vec<String> = shortcut
+                            .keys
+                            .iter()
+                            .map(|keys| match keys {
+                                ShortcutKeys::Shortcut(shortcut) => ctx.format_shortcut(shortcut),
+                                ShortcutKeys::Text(text) => text.to_string(),
+                            })
+                            .collect();
+                        ui.label(egui::RichText::new(keys.join(" / ")).monospace());
+                        ui.label(shortcut.description);
+                        ui.end_row();
+                    }
+                });
+            }
+
+            ui.add_space(10.0);
+            ui.separator();
+            if ui.button("Close").clicked() {
+                close_clicked = true;
+            }
+        });
+
+        if close_clicked || modal.should_close() {
+            self.show_shortcuts = false;
+        }
+    }
+
+    // Selection is kept by index into `notes`, so it survives sorting and
+    // filtering; this only brings the row back into view when it moves.
+    fn track_selected_position(&mut self, visible: &[usize]) {
+        let position = self
+            .selected
+            .and_then(|idx| Some((self.notes.get(idx)?.id, visible.iter().position(|&i| i == idx)?)));
+        self.scroll_to_selected = position.is_some() && position != self.selected_position;
+        self.selected_position = position;
+    }
+
+    fn pinned_here(&self, note: &Note) -> bool {
+        if note.pinned {
+            return true;
+        }
+        let (tags, _) = parse_search_query(&self.search);
+        note.pinned_in.iter().any(|t| tags.contains(t))
+    }
+
+    fn note_list_label(&self, ui: &egui::Ui, note: &Note) -> egui::WidgetText {
+        let preview = if self.settings.show_previews { body_preview(&note.body, 60) } else { String::new() };
+        let mut title = truncate_title(&note.title, self.settings.sidebar_title_max);
+        if let Some(icon) = &note.icon {
+            title = format!("{} {}", icon, title);
+        }
+        if note.no_autosave {
+            title = format!("⏸ {}", title);
+        }
+        if note.locked {
+            title = format!("🔒 {}", title);
+        }
+        if note.is_expired() {
+            title = format!("⌛ {}", title);
+        }
+        if self.pinned_here(note) {
+            title = format!("📌 {}", title);
+        }
+        if preview.is_empty() {
+            return title.into();
+        }
+
+        let body_font = egui::TextStyle::Body.resolve(ui.style());
+        let preview_font = egui::FontId::proportional((body_font.size - 3.0).max(9.0));
+        let mut job = egui::text::LayoutJob::default();
+        job.append(&title, 0.0, egui::TextFormat::simple(body_font, egui::Color32::PLACEHOLDER));
+        job.append("\n", 0.0, egui::TextFormat::simple(preview_font.clone(), egui::Color32::PLACEHOLDER));
+        job.append(&preview, 0.0, egui::TextFormat::simple(preview_font, ui.visuals().weak_text_color()));
+        job.into()
+    }
+
+    fn visible_note_indices(&self) -> Vec<usize> {
+        let (tags, q) = parse_search_query(&self.search);
+        let since = self.filter_preset.map_or(0, FilterPreset::since);
+        let referenced = if self.filter_preset == Some(FilterPreset::Orphaned) {
+            referenced_note_ids(&self.notes)
+        } else {
+            std::collections::HashSet::new()
+        };
+        let mut indices: Vec<usize> = self
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.archived == (self.filter_preset == Some(FilterPreset::Archived)))
+            .filter(|(_, n)| n.modified >= since)
+            .filter(|(_, n)| {
+                self.filter_preset != Some(FilterPreset::Orphaned) || (n.tags.is_empty() && !referenced.contains(&n.id))
+            })
+            .filter(|(_, n)| {
+                tags.iter().all(|tag| n.tags.iter().any(|t| t.to_lowercase() == *tag))
+            })
+            .filter(|(_, n)| {
+                let in_title = || n.title.to_lowercase().contains(&q);
+                let in_body = || n.body.to_lowercase().contains(&q);
+                q.is_empty()
+                    || match self.search_scope {
+                        SearchScope::Title => in_title(),
+                        SearchScope::Body => in_body(),
+                        SearchScope::Both => in_title() || in_body(),
+                    }
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        match self.settings.sort_mode {
+            SortMode::Manual => {}
+            SortMode::Title => indices.sort_by_cached_key(|&i| self.notes[i].title.to_lowercase()),
+            SortMode::Modified => indices.sort_by_key(|&i| self.notes[i].modified),
+            SortMode::Opened => indices.sort_by_key(|&i| self.notes[i].last_opened),
+        }
+        if self.settings.sort_mode != SortMode::Manual && !self.settings.sort_ascending {
+            indices.reverse();
+        }
+        let pinned_here = |note: &Note| note.pinned || note.pinned_in.iter().any(|t| tags.contains(t));
+        indices.sort_by_key(|&i| !pinned_here(&self.notes[i]));
+        if self.settings.float_selected && self.dragging.is_none() {
+            if let Some(pos) = indices.iter().position(|&i| Some(i) == self.selected) {
+                let selected = indices.remove(pos);
+                indices.insert(0, selected);
+            }
+        }
+        indices
+    }
+
+    fn note_row(&self, ui: &mut egui::Ui, selected: bool, label: egui::WidgetText) -> egui::Response {
+        let style = self.settings.selection_style;
+        let fill = selected && style != SelectionStyle::LeftBar;
+        let response = ui.selectable_label(fill, label);
+        if selected && self.focus_list {
+            response.request_focus();
+        }
+        if selected && (self.focus_list || self.scroll_to_selected) {
+            response.scroll_to_me(None);
+        }
+        if selected && style != SelectionStyle::Background {
+            let rect = response.rect;
+            let width = if self.settings.theme == ThemeMode::HighContrast { 6.0 } else { 3.0 };
+            let bar = egui::Rect::from_min_max(rect.left_top(), egui::pos2(rect.left() + width, rect.bottom()));
+            ui.painter().rect_filled(bar, 1.0, ui.visuals().selection.stroke.color);
+        }
+        response
+    }
+
+    fn note_stats_ui(&self, ui: &mut egui::Ui, idx: usize) {
+        let Some(note) = self.notes.get(idx) else {
+            return;
+        };
+        let modified: DateTime<Local> = Local.timestamp_opt(note.modified as i64, 0).unwrap();
+        if truncate_title(&note.title, self.settings.sidebar_title_max) != note.title {
+            ui.label(egui::RichText::new(&note.title).strong());
+        }
+        ui.label(format!("{} words", Self::get_word_count(&note.body, self.settings.word_count_mode)));
+        ui.label(format!("Modified {}", modified.format("%d-%m-%Y %H:%M")));
+        if note.last_opened == 0 {
+            ui.label("Never opened");
+        } else {
+            ui.label(format!("Opened {}", relative_time(current_unix().saturating_sub(note.last_opened))));
+        }
+        if !note.tags.is_empty() {
+            let tags: Vec<String> = note.tags.iter().map(|t| format!("#{}", t)).collect();
+            ui.label(tags.join(" "));
+        }
+    }
+
+    fn move_note(&mut self, from: usize, to: usize) {
+        let len = self.notes.len();
+        if from >= len || to > len || from == to {
+            return;
+        }
+
+        let selected_id = self.selected.and_then(|s| self.notes.get(s).map(|n| n.id));
+
+        let note = self.notes.remove(from);
+
+        let insert_at = if to > from { to - 1 } else { to };
+        let insert_at = insert_at.min(self.notes.len());
+
+        self.notes.insert(insert_at, note);
+
+        self.selected = selected_id.and_then(|id| {
+            self.notes.iter().position(|n| n.id == id)
+        });
+
+        self.dirty = true;
+    }
+
+    fn show_settings_page(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.heading("Settings");
+        ui.separator();
+        ui.add_space(10.0);
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Appearance").size(18.0));
+                ui.add_space(5.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Theme:");
+                    for theme in ThemeMode::ALL {
+                        let response = ui.selectable_label(self.settings.theme == theme, theme.label());
+                        if std::mem::take(&mut self.focus_first_setting) {
+                            response.request_focus();
+                        }
+                        if response.clicked() {
+                            self.settings.theme = theme;
+                            self.apply_theme(ctx);
+                            self.settings_changed = true;
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Open on startup:");
+                    egui::ComboBox::from_id_salt("startup_view")
+                        .selected_text(self.settings.startup_view.label())
+                        .show_ui(ui, |ui| {
+                            for view in StartupView::ALL {
+                                if ui.selectable_label(self.settings.startup_view == view, view.label()).clicked() {
+                                    self.settings.startup_view = view;
+                                    self.settings_changed = true;
+                                }
+                            }
+                        });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Notes list:");
+                    for side in SidebarSide::ALL {
+                        if ui.selectable_label(self.settings.sidebar_side == side, side.label()).clicked() {
+                            self.settings.sidebar_side = side;
+                            self.settings_changed = true;
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Shorten titles in the list after:");
+                    let mut sidebar_title_max = self.settings.sidebar_title_max;
+                    if ui
+                        .add(egui::DragValue::new(&mut sidebar_title_max).range(0..=200).suffix(" characters"))
+                        .on_hover_text("0 shows titles in full. Hover a note to see its whole title.")
+                        .changed()
+                    {
+                        self.settings.sidebar_title_max = sidebar_title_max;
+                        self.settings_changed = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Selected note:");
+                    for style in SelectionStyle::ALL {
+                        if ui.selectable_label(self.settings.selection_style == style, style.label()).clicked() {
+                            self.settings.selection_style = style;
+                            self.settings_changed = true;
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Maximum text width:");
+                    let mut max_editor_width = self.settings.max_editor_width;
+                    if ui
+                        .add(egui::Slider::new(&mut max_editor_width, MIN_EDITOR_WIDTH..=MAX_EDITOR_WIDTH).step_by(50.0))
+                        .changed()
+                    {
+                        self.settings.max_editor_width = max_editor_width;
+                        self.settings_changed = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Font size:");
+                    let mut font_size = self.settings.font_size;
+                    if ui.add(egui::Slider::new(&mut font_size, MIN_FONT_SIZE..=MAX_FONT_SIZE).step_by(1.0)).changed() {
+                        self.settings.font_size = font_size;
+                        self.apply_font_settings(ctx);
+                        self.settings_changed = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Interface scale:");
+                    let mut ui_scale = self.settings.ui_scale;
+                    let response = ui.add(
+                        egui::Slider::new(&mut ui_scale, MIN_UI_SCALE..=MAX_UI_SCALE)
+                            .step_by(0.05)
+                            .custom_formatter(|n, _| format!("{:.0}%", n * 100.0)),
+                    );
+                    if response.changed() {
+                        self.settings.ui_scale = ui_scale;
+                        self.settings_changed = true;
+                    }
+                    if (response.changed() && !response.dragged()) || response.drag_stopped() {
+                        ctx.set_zoom_factor(self.settings.ui_scale);
+                    }
+                });
+
+                ui.add_space(5.0);
+                ui.label("Layout presets:");
+                let mut apply = None;
+                let mut remove = None;
+                for (i, preset) in self.settings.layout_presets.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(self.layout_preset_active(preset), &preset.name).clicked() {
+                            apply = Some(i);
+                        }
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{} · sidebar {:.0} · text {:.0} · font {:.0}{}",
+                                preset.notes_layout.label(),
+                                preset.sidebar_width,
+                                preset.max_editor_width,
+                                preset.font_size,
+                                if preset.show_previews { " · previews" } else { "" }
+                            ))
+                            .size(10.0)
+                            .weak(),
+                        );
+                        if ui.small_button("✖").on_hover_text("Delete preset").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.layout_preset_name).hint_text("Name").desired_width(120.0),
+                    );
+                    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui
+                        .add_enabled(!self.layout_preset_name.trim().is_empty(), egui::Button::new("Save current layout"))
+                        .on_hover_text("Saves the sidebar width, view, list previews, font size and text width; an existing preset with the same name is replaced")
+                        .clicked()
+                        || submitted
+                    {
+                        self.save_layout_preset();
+                    }
+                });
+                if let Some(preset) = apply.map(|i| self.settings.layout_presets[i].clone()) {
+                    self.apply_layout_preset(ctx, &preset);
+                }
+                if let Some(i) = remove {
+                    self.settings.layout_presets.remove(i);
+                    self.settings_changed = true;
+                }
+            });
+
+            ui.add_space(10.0);
+
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Editor").size(18.0));
+                ui.add_space(5.0);
+
+                let mut auto_save = self.settings.auto_save;
+                if ui.checkbox(&mut auto_save, "Auto-save notes").changed() {
+                    self.settings.auto_save = auto_save;
+                    self.settings_changed = true;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Empty note placeholder:");
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut self.settings.body_hint).desired_width(200.0))
+                        .on_hover_text("Shown in the editor until you start typing. Leave blank for none.")
+                        .changed()
+                    {
+                        self.settings_changed = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Add new notes at:");
+                    for position in NewNotePosition::ALL {
+                        if ui.selectable_label(self.settings.new_note_position == position, position.label()).clicked() {
+                            self.settings.new_note_position = position;
+                            self.settings_changed = true;
+                        }
+                    }
+                });
+
+                let mut paper_styles = self.settings.paper_styles;
+                if ui
+                    .checkbox(&mut paper_styles, "Paper styles")
+                    .on_hover_text("Lets each note pick a lined or tinted background behind the editor")
+                    .changed()
+                {
+                    self.settings.paper_styles = paper_styles;
+                    self.settings_changed = true;
+                }
+
+                let mut pomodoro_enabled = self.settings.pomodoro_enabled;
+                if ui
+                    .checkbox(&mut pomodoro_enabled, "Writing session timer")
+                    .on_hover_text("Shows a 🍅 timer in the editor toolbar")
+                    .changed()
+                {
+                    self.settings.pomodoro_enabled = pomodoro_enabled;
+                    if !pomodoro_enabled {
+                        self.pomodoro = None;
+                    }
+                    self.settings_changed = true;
+                }
+                ui.add_enabled_ui(self.settings.pomodoro_enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Work:");
+                        let mut work = self.settings.pomodoro_work_minutes;
+                        if ui.add(egui::DragValue::new(&mut work).range(1..=180).suffix(" min")).changed() {
+                            self.settings.pomodoro_work_minutes = work;
+                            self.settings_changed = true;
+                        }
+                        ui.label("Break:");
+                        let mut rest = self.settings.pomodoro_break_minutes;
+                        if ui.add(egui::DragValue::new(&mut rest).range(1..=60).suffix(" min")).changed() {
+                            self.settings.pomodoro_break_minutes = rest;
+                            self.settings_changed = true;
+                        }
+                    });
+                    let mut pomodoro_notify = self.settings.pomodoro_notify;
+                    if ui.checkbox(&mut pomodoro_notify, "Desktop notification when a session or break ends").changed() {
+                        self.settings.pomodoro_notify = pomodoro_notify;
+                        self.settings_changed = true;
+                    }
+                });
+
+                let mut remember_search = self.settings.remember_search;
+                if ui
+                    .checkbox(&mut remember_search, "Remember last search")
+                    .on_hover_text("Restores the search text, scope and filter the next time the app starts")
+                    .changed()
+                {
+                    self.settings.remember_search = remember_search;
+                    self.settings_changed = true;
+                }
+
+                let mut drag_and_drop = self.settings.drag_and_drop;
+                if ui.checkbox(&mut drag_and_drop, "Enable Drag and Drop").changed() {
+                    self.settings.drag_and_drop = drag_and_drop;
+                    self.settings_changed = true;
+                }
+
+                let mut float_selected = self.settings.float_selected;
+                if ui
+                    .checkbox(&mut float_selected, "Float the selected note to the top of the list")
+                    .changed()
+                {
+                    self.settings.float_selected = float_selected;
+                    self.settings_changed = true;
+                }
+
+                let mut show_word_count = self.settings.show_word_count;
+                if ui.checkbox(&mut show_word_count, "Show word count").changed() {
+                    self.settings.show_word_count = show_word_count;
+                    self.settings_changed = true;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Word count:");
+                    egui::ComboBox::from_id_salt("word_count_mode")
+                        .selected_text(self.settings.word_count_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in WordCountMode::ALL {
+                                if ui.selectable_label(self.settings.word_count_mode == mode, mode.label()).clicked() {
+                                    self.settings.word_count_mode = mode;
+                                    self.settings_changed = true;
+                                }
+                            }
+                        });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Indent with:");
+                    let indent_label = |width: usize, tabs: bool| {
+                        if tabs { "Tab".to_owned() } else { format!("{} spaces", width) }
+                    };
+                    let current = indent_label(self.settings.indent_width, self.settings.indent_with_tabs);
+                    egui::ComboBox::from_id_salt("indent_width")
+                        .selected_text(current)
+                        .show_ui(ui, |ui| {
+                            for (width, tabs) in [(2, false), (4, false), (8, false), (4, true)] {
+                                let selected = self.settings.indent_with_tabs == tabs
+                                    && (tabs || self.settings.indent_width == width);
+                                if ui.selectable_label(selected, indent_label(width, tabs)).clicked() {
+                                    self.settings.indent_width = width;
+                                    self.settings.indent_with_tabs = tabs;
+                                    self.settings_changed = true;
+                                }
+                            }
+                        });
+                });
+
+                let mut continue_lists = self.settings.continue_lists;
+                if ui.checkbox(&mut continue_lists, "Continue lists when pressing Enter").changed() {
+                    self.settings.continue_lists = continue_lists;
+                    self.settings_changed = true;
+                }
+
+                let mut show_previews = self.settings.show_previews;
+                if ui.checkbox(&mut show_previews, "Show previews in notes list").changed() {
+                    self.settings.show_previews = show_previews;
+                    self.settings_changed = true;
+                }
+
+                let mut click_selected_to_edit = self.settings.click_selected_to_edit;
+                if ui
+                    .checkbox(&mut click_selected_to_edit, "Click the selected note again to edit it")
+                    .changed()
+                {
+                    self.settings.click_selected_to_edit = click_selected_to_edit;
+                    self.settings_changed = true;
+                }
+
+                let mut save_on_switch = self.settings.save_on_switch;
+                if ui
+                    .checkbox(&mut save_on_switch, "Save when switching notes")
+                    .on_hover_text("Saves the note you're leaving even when auto-save is off")
+                    .changed()
+                {
+                    self.settings.save_on_switch = save_on_switch;
+                    self.settings_changed = true;
+                }
+
+                let mut confirm_switch_while_editing = self.settings.confirm_switch_while_editing;
+                if ui
+                    .add_enabled(
+                        !self.settings.save_on_switch,
+                        egui::Checkbox::new(&mut confirm_switch_while_editing, "Ask before leaving a note with unsaved edits"),
+                    )
+                    .on_hover_text("Only applies when auto-save and saving on switch are off")
+                    .changed()
+                {
+                    self.settings.confirm_switch_while_editing = confirm_switch_while_editing;
+                    self.settings_changed = true;
+                }
+
+                let mut highlight_unsaved = self.settings.highlight_unsaved;
+                if ui
+                    .checkbox(&mut highlight_unsaved, "Highlight unsaved changes")
+                    .on_hover_text("Shows the unsaved-changes marker in the accent colour when auto-save is off")
+                    .changed()
+                {
+                    self.settings.highlight_unsaved = highlight_unsaved;
+                    self.settings_changed = true;
+                }
+
+                let mut auto_capitalize = self.settings.auto_capitalize;
+                if ui.checkbox(&mut auto_capitalize, "Capitalize the first letter of sentences").changed() {
+                    self.settings.auto_capitalize = auto_capitalize;
+                    self.settings_changed = true;
+                }
+
+                let mut trim_trailing_whitespace = self.settings.trim_trailing_whitespace;
+                if ui.checkbox(&mut trim_trailing_whitespace, "Trim trailing whitespace on save").changed() {
+                    self.settings.trim_trailing_whitespace = trim_trailing_whitespace;
+                    self.settings_changed = true;
+                }
+            });
+
+            ui.add_space(10.0);
+
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Templates").size(18.0));
+                ui.add_space(5.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("New notes use:");
+                    if template_combo(ui, "default_template", &mut self.settings.default_template, &self.settings.templates) {
+                        self.settings_changed = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Daily note title:");
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut self.settings.daily_note_title).desired_width(100.0))
+                        .on_hover_text("A date pattern such as %Y-%m-%d, or a fixed title to always append to the same note")
+                        .changed()
+                    {
+                        self.settings_changed = true;
+                    }
+                    ui.label("from");
+                    if template_combo(ui, "daily_note_template", &mut self.settings.daily_note_template, &self.settings.templates) {
+                        self.settings_changed = true;
+                    }
+                });
+
+                let mut remove = None;
+                for (i, template) in self.settings.templates.iter_mut().enumerate() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let old_name = template.name.clone();
+                        if ui.text_edit_singleline(&mut template.name).changed() {
+                            if self.settings.default_template == old_name {
+                                self.settings.default_template = template.name.clone();
+                            }
+                            if self.settings.daily_note_template == old_name {
+                                self.settings.daily_note_template = template.name.clone();
+                            }
+                            self.settings_changed = true;
+                        }
+                        if ui.button("Delete").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    if ui
+                        .add(egui::TextEdit::multiline(&mut template.body).desired_rows(3).desired_width(f32::INFINITY))
+                        .changed()
+                    {
+                        self.settings_changed = true;
+                    }
+                }
+                if let Some(i) = remove {
+                    let template = self.settings.templates.remove(i);
+                    if self.settings.default_template == template.name {
+                        self.settings.default_template.clear();
+                    }
+                    if self.settings.daily_note_template == template.name {
+                        self.settings.daily_note_template.clear();
+                    }
+                    self.settings_changed = true;
+                }
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Add template").clicked() {
+                        self.settings.templates.push(NoteTemplate {
+                            name: format!("Template {}", self.settings.templates.len() + 1),
+                            body: String::new(),
+                        });
+                        self.settings_changed = true;
+                    }
+                    ui.label(egui::RichText::new("{date} is replaced with today's date").size(10.0).weak());
+                });
+            });
+
+            ui.add_space(10.0);
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Storage Information").size(18.0));
+                ui.add_space(5.0);
+                ui.group(|ui| {
+                    ui.label("Notes stored at:");
+                    ui.label(self.store.dir.to_string_lossy());
+                });
+                ui.group(|ui| {
+                    ui.label("Settings stored at:");
+                    ui.label(&self.settings_path);
+                });
+                ui.label(format!("Total notes: {}", self.notes.len()));
+                if ui.button("Find and replace in all notes…").clicked() {
+                    self.find_replace = Some(FindReplace::default());
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Copy all notes as Markdown").clicked() {
+                        let markdown = all_notes_markdown(&self.notes);
+                        self.storage_status = Some(format!(
+                            "Copied {} notes ({} characters)",
+                            self.notes.iter().filter(|n| !n.archived).count(),
+                            markdown.chars().count()
+                        ));
+                        ctx.copy_text(markdown);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Statistics report:");
+                    if ui.button("Copy").clicked() {
+                        ctx.copy_text(stats_report(&self.notes, self.settings.word_count_mode));
+                        self.storage_status = Some("Copied statistics report".to_owned());
+                    }
+                    if ui.button("Save to").clicked() {
+                        let report = stats_report(&self.notes, self.settings.word_count_mode);
+                        self.storage_status = Some(match fs::write(self.report_path.trim(), report) {
+                            Ok(()) => format!("Saved statistics report to {}", self.report_path.trim()),
+                            Err(e) => format!("Failed to save statistics report: {}", e),
+                        });
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut self.report_path).desired_width(f32::INFINITY));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Import folder:");
+                    let enabled = !self.import_folder_path.trim().is_empty();
+                    if ui
+                        .add_enabled(enabled, egui::Button::new("Import"))
+                        .on_hover_text("Adds every .md and .txt file below the folder as a note, tagged with its subfolders")
+                        .clicked()
+                    {
+                        self.import_notes_folder();
+                    }
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.import_folder_path)
+                            .hint_text("Folder path")
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Import bundle:");
+                    let enabled = !self.import_bundle_path.trim().is_empty();
+                    if ui
+                        .add_enabled(enabled, egui::Button::new("Import"))
+                        .on_hover_text("Adds the note from a .zip bundle and copies its attachments")
+                        .clicked()
+                    {
+                        let path = std::path::PathBuf::from(self.import_bundle_path.trim());
+                        self.import_note_bundle(&path);
+                    }
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.import_bundle_path)
+                            .hint_text("Bundle path")
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+                if let Some(status) = &self.storage_status {
+                    ui.label(egui::RichText::new(status).size(10.0).weak());
+                }
+                let mut compact_storage = self.settings.compact_storage;
+                if ui
+                    .checkbox(&mut compact_storage, "Store notes as compact JSON")
+                    .on_hover_text("Smaller files that load faster, but are harder to read by hand. All notes are rewritten on the next save.")
+                    .changed()
+                {
+                    self.set_compact_storage(compact_storage);
+                }
+                let mut prune_untitled = self.settings.prune_untitled;
+                if ui
+                    .checkbox(&mut prune_untitled, "Remove empty untitled notes on exit")
+                    .on_hover_text("Only notes that still have their default title and were never edited")
+                    .changed()
+                {
+                    self.settings.prune_untitled = prune_untitled;
+                    self.settings_changed = true;
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Retry failed saves at most every:");
+                    let mut save_retry_max_seconds = self.settings.save_retry_max_seconds;
+                    if ui
+                        .add(egui::DragValue::new(&mut save_retry_max_seconds).range(5..=600).suffix(" s"))
+                        .on_hover_text("After a save fails, auto-save waits 2 s and doubles the wait after each further failure, up to this limit")
+                        .changed()
+                    {
+                        self.settings.save_retry_max_seconds = save_retry_max_seconds;
+                        self.settings_changed = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Warn when notes reach:");
+                    let mut note_soft_limit = self.settings.note_soft_limit;
+                    if ui
+                        .add(egui::DragValue::new(&mut note_soft_limit).range(0..=100_000).speed(10))
+                        .on_hover_text("0 disables the warning. Creating notes is never blocked.")
+                        .changed()
+                    {
+                        self.settings.note_soft_limit = note_soft_limit;
+                        self.settings_changed = true;
+                    }
+                });
+            });
+
+            ui.add_space(10.0);
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Quick Capture").size(18.0));
+                ui.add_space(5.0);
+
+                let mut quick_capture_enabled = self.settings.quick_capture_enabled;
+                if ui.checkbox(&mut quick_capture_enabled, "Start a new note with a shortcut").changed() {
+                    self.settings.quick_capture_enabled = quick_capture_enabled;
+                    self.settings_changed = true;
+                }
+
+                ui.add_enabled_ui(self.settings.quick_capture_enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Shortcut:");
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut self.settings.quick_capture_shortcut).desired_width(120.0))
+                            .on_hover_text("For example Ctrl+Alt+N or Alt+Shift+Space")
+                            .changed()
+                        {
+                            self.settings_changed = true;
+                        }
+                    });
+                    if let Err(error) = parse_shortcut(&self.settings.quick_capture_shortcut) {
+                        ui.label(egui::RichText::new(error).size(10.0).color(ui.visuals().error_fg_color));
+                    }
+                    ui.label(
+                        egui::RichText::new(
+                            "Works while Notes has focus. To capture from anywhere, bind a system shortcut to \"notes --capture\".",
+                        )
+                        .size(10.0)
+                        .weak(),
+                    );
+                });
+            });
+
+            ui.add_space(10.0);
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("Local API").size(18.0));
+                ui.add_space(5.0);
+
+                let mut api_enabled = self.settings.api_enabled;
+                if ui
+                    .checkbox(&mut api_enabled, "Accept requests from scripts on this computer")
+                    .on_hover_text("Serves GET /notes, POST /notes and GET /notes/{id} on 127.0.0.1")
+                    .changed()
+                {
+                    self.settings.api_enabled = api_enabled;
+                    if api_enabled && self.settings.api_token.is_empty() {
+                        self.settings.api_token = format!("{:032x}", rand::random::<u128>());
+                    }
+                    self.settings_changed = true;
+                }
+
+                ui.add_enabled_ui(self.settings.api_enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Port:");
+                        let mut api_port = self.settings.api_port;
+                        if ui.add(egui::DragValue::new(&mut api_port).range(1024..=65535)).changed() {
+                            self.settings.api_port = api_port;
+                            self.settings_changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Token:");
+                        ui.label(egui::RichText::new(&self.settings.api_token).monospace());
+                        if ui.button("Copy").clicked() {
+                            ctx.copy_text(self.settings.api_token.clone());
+                        }
+                        if ui.button("Regenerate").clicked() {
+                            self.settings.api_token = format!("{:032x}", rand::random::<u128>());
+                            self.settings_changed = true;
+                        }
+                    });
+                    ui.label(egui::RichText::new("Send it as \"Authorization: Bearer <token>\"").size(10.0).weak());
+                });
+
+                if let Some(error) = &self.api_error {
+                    ui.label(egui::RichText::new(error).size(10.0).color(ui.visuals().error_fg_color));
+                } else if self.settings.api_enabled && self.read_only {
+                    ui.label(egui::RichText::new("Not running while Notes is open read-only").size(10.0).weak());
+                }
+            });
+
+            ui.add_space(20.0);
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Reset to Defaults").clicked() {
+                    self.reset_settings(ctx);
+                }
+                if ui
+                    .button("Reset Layout (sidebar and window size)")
+                    .on_hover_text("Restores the default sidebar width and window size")
+                    .clicked()
+                {
+                    self.reset_layout(ctx);
+                }
+            });
+
+            let focused = ctx.memory(|m| m.focused());
+            if focused != self.settings_focus {
+                self.settings_focus = focused;
+                if let Some(response) = focused.and_then(|id| ctx.read_response(id)) {
+                    ui.scroll_to_rect(response.rect, None);
+                }
+            }
+        });
+    }
+
+    fn set_compact_storage(&mut self, compact: bool) {
+        self.settings.compact_storage = compact;
+        self.store.compact = compact;
+        self.store.order.clear();
+        self.settings_changed = true;
+        self.dirty = true;
+    }
+
+    fn reset_settings(&mut self, ctx: &egui::Context) {
+        let scratchpad = std::mem::take(&mut self.settings.scratchpad);
+        let templates = std::mem::take(&mut self.settings.templates);
+        let saved_searches = std::mem::take(&mut self.settings.saved_searches);
+        let layout_presets = std::mem::take(&mut self.settings.layout_presets);
+        let compact_storage = self.settings.compact_storage;
+        self.settings = AppSettings::default();
+        self.settings.scratchpad = scratchpad;
+        self.settings.templates = templates;
+        self.settings.saved_searches = saved_searches;
+        self.settings.layout_presets = layout_presets;
+        if self.settings.compact_storage != compact_storage {
+            self.set_compact_storage(self.settings.compact_storage);
+        }
+        self.apply_theme(ctx);
+        self.apply_font_settings(ctx);
+        ctx.set_zoom_factor(self.settings.ui_scale);
+        self.settings_changed = true;
+    }
+
+    fn current_layout_preset(&self, name: String) -> LayoutPreset {
+        LayoutPreset {
+            name,
+            sidebar_width: self.sidebar_width,
+            notes_layout: self.settings.notes_layout,
+            show_previews: self.settings.show_previews,
+            font_size: self.settings.font_size,
+            max_editor_width: self.settings.max_editor_width,
+        }
+    }
+
+    fn layout_preset_active(&self, preset: &LayoutPreset) -> bool {
+        (preset.sidebar_width - self.sidebar_width).abs() < 1.0
+            && preset.notes_layout == self.settings.notes_layout
+            && preset.show_previews == self.settings.show_previews
+            && preset.font_size == self.settings.font_size
+            && preset.max_editor_width == self.settings.max_editor_width
+    }
+
+    fn apply_layout_preset(&mut self, ctx: &egui::Context, preset: &LayoutPreset) {
+        self.pending_sidebar_width = Some(preset.sidebar_width.max(150.0));
+        self.settings.notes_layout = preset.notes_layout;
+        self.settings.show_previews = preset.show_previews;
+        self.settings.font_size = preset.font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        self.settings.max_editor_width = preset.max_editor_width.clamp(MIN_EDITOR_WIDTH, MAX_EDITOR_WIDTH);
+        self.apply_theme(ctx);
+        self.apply_font_settings(ctx);
+        self.settings_changed = true;
+    }
+
+    fn save_layout_preset(&mut self) {
+        let name = self.layout_preset_name.trim().to_owned();
+        if name.is_empty() {
+            return;
+        }
+        let preset = self.current_layout_preset(name);
+        match self.settings.layout_presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.settings.layout_presets.push(preset),
+        }
+        self.layout_preset_name.clear();
+        self.settings_changed = true;
+    }
+
+    fn reset_layout(&mut self, ctx: &egui::Context) {
+        self.pending_sidebar_width = Some(DEFAULT_SIDEBAR_WIDTH);
+        self.session.window = None;
+        self.save_session();
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(DEFAULT_WINDOW_SIZE));
+    }
+
+    fn get_word_count(text: &str, mode: WordCountMode) -> usize {
+        match mode {
+            WordCountMode::Whitespace => text.split_whitespace().count(),
+            WordCountMode::Cjk => {
+                let mut count = 0;
+                let mut in_word = false;
+                for c in text.chars() {
+                    if is_cjk(c) {
+                        count += 1;
+                        in_word = false;
+                    } else if c.is_whitespace() || is_cjk_punctuation(c) {
+                        in_word = false;
+                    } else if !in_word {
+                        count += 1;
+                        in_word = true;
+                    }
+                }
+                count
+            }
+        }
+    }
+}
+
+impl eframe::App for NotesApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        static mut THEME_APPLIED: bool = false;
+        static mut FONT_SET: bool = false;
+
+        unsafe {
+            if !THEME_APPLIED {
+                self.apply_theme(ctx);
+                THEME_APPLIED = true;
+            }
+
+            if !FONT_SET {
+                self.apply_font_settings(ctx);
+                ctx.set_zoom_factor(self.settings.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE));
+                FONT_SET = true;
+            }
+        }
+
+        self.handle_shortcuts(ctx);
+        self.handle_escape(ctx);
+        self.track_window_geometry(ctx);
+
+        if !self.zen_mode {
+            egui::TopBottomPanel::top("top_panel")
+                .frame(egui::Frame::default()
+                    .fill(ctx.style().visuals.panel_fill)
+                    .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 10 })
+                    .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                )
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(self.current_view == AppView::Notes, "Notes").clicked() {
+                            self.current_view = AppView::Notes;
+                        }
+                        if ui.selectable_label(self.current_view == AppView::Scratchpad, "Scratchpad").clicked() {
+                            self.current_view = AppView::Scratchpad;
+                        }
+                        if ui.selectable_label(self.current_view == AppView::Tags, "Tags").clicked() {
+                            self.current_view = AppView::Tags;
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
+                            if ui.selectable_label(self.current_view == AppView::Settings, "⚙").clicked() {
+                                self.current_view = AppView::Settings;
+                            }
+                            self.show_quick_access_menu(ctx, ui);
+                            if self.current_view == AppView::Notes {
+                                let (layout, icon, hint) = match self.settings.notes_layout {
+                                    NotesLayout::List => (NotesLayout::Grid, "▦", "Show notes as a grid of cards"),
+                                    NotesLayout::Grid => (NotesLayout::List, "☰", "Show the notes list and editor"),
+                                };
+                                if ui.button(icon).on_hover_text(hint).clicked() {
+                                    self.settings.notes_layout = layout;
+                                    self.settings_changed = true;
+                                }
+                                if !self.settings.layout_presets.is_empty() {
+                                    let mut apply = None;
+                                    ui.menu_button("◫", |ui| {
+                                        for preset in &self.settings.layout_presets {
+                                            if ui.selectable_label(self.layout_preset_active(preset), &preset.name).clicked() {
+                                                apply = Some(preset.clone());
+                                                ui.close();
+                                            }
+                                        }
+                                    })
+                                    .response
+                                    .on_hover_text("Layout presets");
+                                    if let Some(preset) = apply {
+                                        self.apply_layout_preset(ctx, &preset);
+                                    }
+                                }
+                            }
+                            self.show_saved_indicator(ui);
+                            if !self.settings.auto_save && self.has_pending_changes() {
+                                if ui
+                                    .small_button("Discard all")
+                                    .on_hover_text("Reload every note from disk, dropping unsaved changes")
+                                    .clicked()
+                                {
+                                    self.discard_all_prompt = true;
+                                }
+                                let marker = egui::RichText::new("● Unsaved changes").size(10.0);
+                                ui.label(if self.settings.highlight_unsaved {
+                                    marker.color(ui.visuals().selection.stroke.color)
+                                } else {
+                                    marker.weak()
+                                });
+                            }
+                            if self.read_only {
+                                ui.label(egui::RichText::new("Read-only").size(10.0).weak())
+                                    .on_hover_text("Another instance is open; changes to notes, settings and attachments won't be saved");
+                            }
+                        });
+                    });
+                });
+        }
+
+        self.show_save_failure_banner(ctx);
+        self.show_load_problems_banner(ctx);
+        self.show_command_bar(ctx);
+
+        match self.current_view {
+            AppView::Notes if self.zen_mode => {
+                self.show_zen_editor(ctx);
+            }
+            AppView::Settings => {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 20, right: 20 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .show(ctx, |ui| {
+                        self.show_settings_page(ctx, ui);
+                    });
+            }
+            AppView::Tags => {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 20, right: 20 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .show(ctx, |ui| {
+                        self.show_tags_page(ui);
+                    });
+            }
+            AppView::Scratchpad => {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 20, right: 20 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .show(ctx, |ui| {
+                        ui.heading("Scratchpad");
+                        ui.separator();
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            let scratchpad = egui::TextEdit::multiline(&mut self.settings.scratchpad)
+                                .hint_text("Jot something down…")
+                                .desired_width(f32::INFINITY)
+                                .desired_rows(20);
+                            if ui.add(scratchpad).changed() {
+                                self.settings_changed = true;
+                            }
+                        });
+                    });
+            }
+            AppView::Notes if self.settings.notes_layout == NotesLayout::Grid => {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 20, right: 20 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .show(ctx, |ui| {
+                        self.show_notes_grid(ui);
+                    });
+            }
+            AppView::Notes => {
+                let mut sidebar = egui::SidePanel::new(self.settings.sidebar_side.panel_side(), "left_panel")
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 10 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .default_width(DEFAULT_SIDEBAR_WIDTH)
+                    .min_width(150.0);
+                if let Some(width) = self.pending_sidebar_width.take() {
+                    sidebar = sidebar.exact_width(width);
+                }
+                let sidebar = sidebar.show(ctx, |ui| {
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            if self.current_view == AppView::Notes {
+                                let new_response = ui.button("New").on_hover_text("Right-click for more options");
+                                if new_response.clicked() {
+                                    self.add_note();
+                                }
+                                new_response.context_menu(|ui| {
+                                    if ui.button("Open daily note").clicked() {
+                                        self.open_daily_note(ui.ctx());
+                                        ui.close();
+                                    }
+                                    if ui.button("Paste note from JSON…").clicked() {
+                                        self.paste_json = Some(String::new());
+                                        ui.close();
+                                    }
+                                });
+                                let editing_selected = self.selected_note_editing();
+                                let delete_response = ui.add_enabled(!editing_selected, egui::Button::new("Delete"));
+                                if delete_response.on_disabled_hover_text("Finish editing before deleting this note").clicked() {
+                                    self.delete_selected();
+                                }
+                                if ui.toggle_value(&mut self.multi_select, "Select").changed() && !self.multi_select {
+                                    self.checked.clear();
+                                }
+                            }
+                        });
+                        ui.add_space(2.0);
+                        ui.separator();
+                        ui.add_space(5.0);
+                        ui.horizontal(|ui| {
+                            ui.label("Search:");
+                            let response = ui.add(
+                                egui::TextEdit::singleline(&mut self.search)
+                                    .id(egui::Id::new("note_search"))
+                                    .hint_text("text or tag:name")
+                                    .desired_width(110.0),
+                            );
+                            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                self.commit_preview();
+                            }
+                            self.preview_selection = if response.has_focus() && !self.search.trim().is_empty() {
+                                self.visible_note_indices().first().map(|&i| self.notes[i].id)
+                            } else {
+                                None
+                            };
+                            if ui
+                                .add_enabled(!self.search.trim().is_empty(), egui::Button::new("☆"))
+                                .on_hover_text("Save this search")
+                                .clicked()
+                            {
+                                self.saved_search_name = Some(self.search.trim().to_owned());
+                            }
+                        });
+                        if let Some(name) = &mut self.saved_search_name {
+                            let mut save = false;
+                            let mut cancel = false;
+                            ui.horizontal(|ui| {
+                                let response = ui.add(egui::TextEdit::singleline(name).hint_text("Name").desired_width(100.0));
+                                save = ui.button("Save").clicked()
+                                    || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
+                                cancel = ui.button("Cancel").clicked();
+                            });
+                            if save && !name.trim().is_empty() {
+                                let name = name.trim().to_owned();
+                                let query = self.search.trim().to_owned();
+                                match self.settings.saved_searches.iter_mut().find(|s| s.name == name) {
+                                    Some(existing) => existing.query = query,
+                                    None => self.settings.saved_searches.push(SavedSearch { name, query }),
+                                }
+                                self.settings_changed = true;
+                                self.saved_search_name = None;
+                            } else if cancel {
+                                self.saved_search_name = None;
+                            }
+                        }
+                        if !self.settings.saved_searches.is_empty() {
+                            let mut remove = None;
+                            ui.horizontal_wrapped(|ui| {
+                                for (i, saved) in self.settings.saved_searches.iter().enumerate() {
+                                    let response = ui
+                                        .selectable_label(self.search == saved.query, &saved.name)
+                                        .on_hover_text(format!("{}\nRight-click to delete", saved.query));
+                                    if response.clicked() {
+                                        self.search = saved.query.clone();
+                                    }
+                                    response.context_menu(|ui| {
+                                        if ui.button("Delete saved search").clicked() {
+                                            remove = Some(i);
+                                            ui.close();
+                                        }
+                                    });
+                                }
+                            });
+                            if let Some(i) = remove {
+                                self.settings.saved_searches.remove(i);
+                                self.settings_changed = true;
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("In:");
+                            for scope in SearchScope::ALL {
+                                if ui.selectable_label(self.search_scope == scope, scope.label()).clicked() {
+                                    self.search_scope = scope;
+                                }
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Sort:");
+                            let mut sort_mode = self.settings.sort_mode;
+                            egui::ComboBox::from_id_salt("sort_mode")
+                                .selected_text(sort_mode.label())
+                                .show_ui(ui, |ui| {
+                                    for mode in SortMode::ALL {
+                                        ui.selectable_value(&mut sort_mode, mode, mode.label());
+                                    }
+                                });
+                            if sort_mode != self.settings.sort_mode {
+                                self.settings.sort_mode = sort_mode;
+                                self.settings_changed = true;
+                            }
+
+                            let direction = if self.settings.sort_ascending { "⬆" } else { "⬇" };
+                            let direction_hint = if self.settings.sort_ascending { "Ascending" } else { "Descending" };
+                            if ui
+                                .add_enabled(self.settings.sort_mode != SortMode::Manual, egui::Button::new(direction))
+                                .on_hover_text(direction_hint)
+                                .clicked()
+                            {
+                                self.settings.sort_ascending = !self.settings.sort_ascending;
+                                self.settings_changed = true;
+                            }
+                        });
+                        ui.horizontal_wrapped(|ui| {
+                            for preset in FilterPreset::ALL {
+                                let active = self.filter_preset == Some(preset);
+                                let mut response = ui.selectable_label(active, preset.label());
+                                if preset == FilterPreset::Orphaned {
+                                    response = response.on_hover_text("Notes with no tags that no other note links to");
+                                }
+                                if response.clicked() {
+                                    self.filter_preset = if active { None } else { Some(preset) };
+                                }
+                            }
+                        });
+                        ui.add_space(2.0);
+                        ui.separator();
+                        ui.add_space(2.0);
+
+                        if self.multi_select {
+                            self.show_batch_tag_panel(ui);
+                            ui.add_space(2.0);
+                            ui.separator();
+                            ui.add_space(2.0);
+                        }
+
+                        let filtered_notes: Vec<(usize, String, egui::WidgetText)> = self
+                            .visible_note_indices()
+                            .into_iter()
+                            .map(|i| (i, self.notes[i].title.clone(), self.note_list_label(ui, &self.notes[i])))
+                            .collect();
+                        let visible: Vec<usize> = filtered_notes.iter().map(|(i, _, _)| *i).collect();
+                        self.track_selected_position(&visible);
+
+                        let mut to_select: Option<usize> = None;
+                        let mut move_from_to: Option<(usize, usize)> = None;
+                        let mut note_action: Option<(usize, NoteAction)> = None;
+
+                        let enable_dnd = self.settings.sort_mode == SortMode::Manual;
+
+                        let line_height = ui.text_style_height(&egui::TextStyle::Body);
+                        let spacing = ui.spacing().item_spacing.y;
+                        let word_count_height = if self.settings.show_word_count { line_height + spacing } else { 0.0 };
+                        let session_active = self.session_notes_created > 0 || self.session_words_written > 0;
+                        let session_height = if session_active { line_height + spacing } else { 0.0 };
+                        let bottom_content_height = line_height
+                            + if enable_dnd { line_height + spacing } else { 0.0 }
+                            + word_count_height
+                            + session_height
+                            + spacing * 2.0;
+
+                        let available_height = ui.available_height() - bottom_content_height;
+
+                        let mut item_rects: Vec<(usize, usize, egui::Rect)> = Vec::new();
+
+                        egui::ScrollArea::vertical()
+                            .max_height(available_height)
+                            .show(ui, |ui| {
+                                for (display_idx, (original_idx, title, label)) in filtered_notes.iter().enumerate() {
+                                    let selected = Some(*original_idx) == self.selected;
+
+                                    if enable_dnd && self.settings.drag_and_drop {
+                                        ui.horizontal(|ui| {
+                                            let base_font_size = 14.0;
+                                            let scale_factor = self.settings.font_size / base_font_size;
+                                            let handle_width = (10.0 * scale_factor).max(8.0);
+                                            let handle_height = (16.0 * scale_factor).max(12.0);
+                                            let handle_size = egui::vec2(handle_width, handle_height);
+                                            
+                                            let editing = self.notes[*original_idx].editing;
+                                            let handle_sense = if editing { egui::Sense::hover() } else { egui::Sense::click_and_drag() };
+                                            let handle_response = ui.allocate_response(handle_size, handle_sense);
+                                            let handle_response = if editing {
+                                                handle_response.on_hover_text("Finish editing to reorder this note")
+                                            } else {
+                                                handle_response
+                                            };
+
+                                            let painter = ui.painter();
+                                            let handle_rect = handle_response.rect;
+                                            let handle_color = if handle_response.hovered() && !editing {
+                                                ui.visuals().text_color()
+                                            } else {
+                                                ui.visuals().weak_text_color()
+                                            };
+
+                                            let line_spacing = (4.0 * scale_factor).max(2.0);
+                                            let line_width = (8.0 * scale_factor).max(6.0);
+                                            let line_thickness = (1.5 * scale_factor).max(1.0);
+                                            
+                                            let center_x = handle_rect.center().x;
+                                            let center_y = handle_rect.center().y;
+                                            
+                                            for i in 0..3 {
+                                                let y = center_y + (i as f32 - 1.0) * line_spacing;
+                                                painter.line_segment(
+                                                    [
+                                                        egui::pos2(center_x - line_width / 2.0, y),
+                                                        egui::pos2(center_x + line_width / 2.0, y)
+                                                    ],
+                                                    egui::Stroke::new(line_thickness, handle_color)
+                                                );
+                                            }
+
+                                            let remaining_width = ui.available_width();
+                                            if self.multi_select {
+                                                self.show_check_box(ui, *original_idx);
+                                            }
+                                            let response = self
+                                                .note_row(ui, selected, label.clone())
+                                                .on_hover_ui(|ui| self.note_stats_ui(ui, *original_idx));
+                                            if response.clicked() {
+                                                to_select = Some(*original_idx);
+                                            }
+                                            response.context_menu(|ui| self.note_context_menu(ui, *original_idx, &mut note_action));
+
+                                            let item_height = (20.0 * scale_factor).max(16.0).max(response.rect.height());
+                                            let full_rect = egui::Rect::from_min_size(
+                                                egui::pos2(handle_rect.min.x, handle_rect.min.y.min(response.rect.min.y)),
+                                                egui::vec2(handle_rect.width() + remaining_width, item_height)
+                                            );
+                                            item_rects.push((display_idx, *original_idx, full_rect));
+
+                                            if handle_response.drag_started() {
+                                                self.dragging = Some(*original_idx);
+                                                self.drag_start_pos = ctx.pointer_latest_pos();
+                                            }
+
+                                            if let Some(dragging_idx) = self.dragging {
+                                                if dragging_idx == *original_idx {
+                                                    if let (Some(pointer_pos), Some(start_pos)) = (ctx.pointer_latest_pos(), self.drag_start_pos) {
+                                                        let offset = pointer_pos - start_pos;
+                                                        let dragged_rect = full_rect.translate(egui::vec2(0.0, offset.y));
+
+                                                        let painter = ui.painter();
+                                                        painter.rect_filled(
+                                                            dragged_rect,
+                                                            4.0,
+                                                            egui::Color32::from_rgba_premultiplied(30, 30, 30, 160)
+                                                        );
+                                                        painter.text(
+                                                            dragged_rect.left_center() + egui::vec2(20.0 * scale_factor, 0.0),
+                                                            egui::Align2::LEFT_CENTER,
+                                                            title,
+                                                            egui::FontId::proportional(self.settings.font_size),
+                                                            egui::Color32::WHITE
+                                                        );
+                                                    }
+                                                }
+                                            }
+                                        });
+
+                                        if let (Some(dragging_idx), Some(pointer_pos)) = (self.dragging, ctx.pointer_latest_pos()) {
+                                            let painter = ui.painter();
+                                            for (_didx, target_orig_idx, rect) in item_rects.iter() {
+                                                if *target_orig_idx != dragging_idx && rect.contains(pointer_pos) {
+                                                    let y = if pointer_pos.y < rect.center().y {
+                                                        rect.top()
+                                                    } else {
+                                                        rect.bottom()
+                                                    };
+                                                    painter.hline(
+                                                        rect.x_range(),
+                                                        y,
+                                                        egui::Stroke::new(2.0, egui::Color32::GRAY)
+                                                    );
+                                                }
+                                            }
+                                        }
+                                    } else {
+                                        ui.horizontal(|ui| {
+                                            if self.multi_select {
+                                                self.show_check_box(ui, *original_idx);
+                                            }
+                                            let response = self
+                                                .note_row(ui, selected, label.clone())
+                                                .on_hover_ui(|ui| self.note_stats_ui(ui, *original_idx));
+                                            if response.clicked() {
+                                                to_select = Some(*original_idx);
+                                            }
+                                            response.context_menu(|ui| self.note_context_menu(ui, *original_idx, &mut note_action));
+                                        });
+                                    }
+                                }
+                            });
+
+                        self.focus_list = false;
+
+                        if self.dragging.is_some() && ctx.input(|i| i.pointer.any_released()) {
+                            if let Some(pointer_pos) = ctx.pointer_latest_pos() {
+                                let mut found: Option<usize> = None;
+                                for (display_idx, _, rect) in item_rects.iter() {
+                                    if rect.contains(pointer_pos) {
+                                        let desired = if pointer_pos.y < rect.center().y { *display_idx } else { *display_idx + 1 };
+                                        found = Some(desired);
+                                        break;
+                                    }
+                                }
+
+                                let desired = if let Some(d) = found {
+                                    d
+                                } else if !item_rects.is_empty() {
+                                    let first_rect = &item_rects[0].2;
+                                    if pointer_pos.y < first_rect.center().y {
+                                        0
+                                    } else {
+                                        item_rects.len()
+                                    }
+                                } else {
+                                    0
+                                };
+                                // Only visible notes have rects, so dropping past the last one lands right after it and
+                                // any hidden notes further down stay where they are.
+                                let desired = match item_rects.get(desired) {
+                                    Some((_, original_idx, _)) => *original_idx,
+                                    None => item_rects.last().map_or(self.notes.len(), |(_, original_idx, _)| original_idx + 1),
+                                };
+                                move_from_to = Some((self.dragging.unwrap(), desired));
+                            }
+
+                            self.dragging = None;
+                            self.drag_start_pos = None;
+                        }
+
+                        if let Some((from, to)) = move_from_to {
+                            self.move_note(from, to);
+                        }
+
+                        if let Some((idx, action)) = note_action {
+                            self.apply_note_action(idx, action);
+                        }
+
+                        if let Some(s) = to_select {
+                            if !self.prepare_switch(s) {
+                                to_select = None;
+                            }
+                        }
+
+                        if let Some(s) = to_select {
+                            if self.selected == Some(s) && self.settings.click_selected_to_edit {
+                                if let Some(note) = self.notes.get_mut(s) {
+                                    note.start_editing();
+                                }
+                            }
+                            self.pick_note(s);
+                        }
+
+                        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+                            ui.label(format!("{} notes", self.notes.len()));
+                            let limit = self.settings.note_soft_limit;
+                            if limit > 0 && self.notes.len() * 10 >= limit * 9 {
+                                ui.label(
+                                    egui::RichText::new(format!("Approaching {} notes, consider archiving old ones", limit))
+                                        .size(10.0)
+                                        .color(ui.visuals().warn_fg_color),
+                                );
+                            }
+                            if session_active {
+                                ui.label(
+                                    egui::RichText::new(format!(
+                                        "This session: {} new {}, {} words written",
+                                        self.session_notes_created,
+                                        if self.session_notes_created == 1 { "note" } else { "notes" },
+                                        self.session_words_written
+                                    ))
+                                    .size(10.0)
+                                    .weak(),
+                                );
+                            }
+                            if self.settings.show_word_count {
+                                let selected_note = self.selected.and_then(|idx| self.notes.get(idx));
+                                let word_count_label = match selected_note {
+                                    Some(note) => format!(
+                                        "{} words in note",
+                                        Self::get_word_count(&note.body, self.settings.word_count_mode)
+                                    ),
+                                    None => {
+                                        let mode = self.settings.word_count_mode;
+                                        let total: usize = self.notes.iter().map(|n| Self::get_word_count(&n.body, mode)).sum();
+                                        format!("{} words in all notes", total)
+                                    }
+                                };
+                                ui.label(word_count_label);
+                            }
+                            if enable_dnd && self.settings.drag_and_drop {
+                                ui.label(egui::RichText::new("Drag handles to reorder").size(10.0));
+                            }
+                            ui.separator();
+                        });
+                    });
+                });
+                self.sidebar_width = sidebar.response.rect.width();
+
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::default()
+                        .fill(ctx.style().visuals.panel_fill)
+                        .inner_margin(egui::Margin { top: 10, bottom: 10, left: 10, right: 15 })
+                        .stroke(egui::Stroke::new(0.0, egui::Color32::TRANSPARENT))
+                    )
+                    .show(ctx, |ui| {
+                        let preview = self
+                            .preview_selection
+                            .and_then(|id| self.notes.iter().position(|n| n.id == id))
+                            .filter(|&i| Some(i) != self.selected);
+                        if let Some(idx) = preview {
+                            self.show_search_preview(ui, idx);
+                        } else if let Some(idx) = self.selected {
+                            if idx < self.notes.len() {
+                                let visible = self.visible_note_indices();
+                                let position_label = visible
+                                    .iter()
+                                    .position(|&i| i == idx)
+                                    .map(|p| format!("Note {} of {}", p + 1, visible.len()));
+
+                                let note = &mut self.notes[idx];
+
+                                ui.horizontal(|ui| {
+                                    if note.editing {
+                                        ui.label("Title:");
+                                        let icon_label = note.icon.clone().unwrap_or_else(|| "☺".to_owned());
+                                        ui.menu_button(icon_label, |ui| {
+                                            egui::Grid::new("note_icon_grid").show(ui, |ui| {
+                                                for (i, icon) in NOTE_ICONS.iter().enumerate() {
+                                                    if ui.selectable_label(note.icon.as_deref() == Some(*icon), *icon).clicked() {
+                                                        note.icon = Some((*icon).to_owned());
+                                                        note.modified = current_unix();
+                                                        self.dirty = true;
+                                                        ui.close();
+                                                    }
+                                                    if i % 8 == 7 {
+                                                        ui.end_row();
+                                                    }
+                                                }
+                                            });
+                                            if ui.add_enabled(note.icon.is_some(), egui::Button::new("No icon")).clicked() {
+                                                note.icon = None;
+                                                note.modified = current_unix();
+                                                self.dirty = true;
+                                                ui.close();
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("Note icon");
+                                        if ui.text_edit_singleline(&mut note.title).changed() {
+                                            note.modified = current_unix();
+                                            if self.settings.auto_save {
+                                                self.dirty = true;
+                                            }
+                                        }
+                                    } else {
+                                        ui.label("");
+                                        if let Some(icon) = &note.icon {
+                                            ui.label(egui::RichText::new(icon).heading());
+                                        }
+                                        ui.label(egui::RichText::new(&note.title).heading());
+                                    }
+
+                                    if let Some(position_label) = &position_label {
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                            ui.label(egui::RichText::new(position_label).size(10.0));
+                                        });
+                                    }
+                                });
+
+                                ui.separator();
+
+                                if note.editing {
+                                    let body_id = egui::Id::new("note_body").with(note.id);
+                                    let mut format_action: Option<MarkdownFormat> = None;
+                                    let mut pomodoro_action: Option<bool> = None;
+
+                                    ui.horizontal(|ui| {
+                                        ui.label("Body:");
+                                        ui.add_space(10.0);
+                                        if ui.button(egui::RichText::new("B").strong()).on_hover_text("Bold").clicked() {
+                                            format_action = Some(MarkdownFormat::Bold);
+                                        }
+                                        if ui.button(egui::RichText::new("I").italics()).on_hover_text("Italic").clicked() {
+                                            format_action = Some(MarkdownFormat::Italic);
+                                        }
+                                        if ui.button("H").on_hover_text("Heading").clicked() {
+                                            format_action = Some(MarkdownFormat::Heading);
+                                        }
+                                        if ui.button("•").on_hover_text("List").clicked() {
+                                            format_action = Some(MarkdownFormat::List);
+                                        }
+                                        if self.settings.paper_styles {
+                                            ui.add_space(10.0);
+                                            let mut paper = note.paper;
+                                            egui::ComboBox::from_id_salt(("note_paper", note.id))
+                                                .selected_text(paper.label())
+                                                .width(70.0)
+                                                .show_ui(ui, |ui| {
+                                                    for style in PaperStyle::ALL {
+                                                        ui.selectable_value(&mut paper, style, style.label());
+                                                    }
+                                                });
+                                            if paper != note.paper {
+                                                note.paper = paper;
+                                                note.modified = current_unix();
+                                                self.dirty = true;
+                                            }
+                                        }
+                                        if self.settings.pomodoro_enabled {
+                                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                                pomodoro_action = pomodoro_widget(
+                                                    ui,
+                                                    self.pomodoro.as_ref(),
+                                                    self.pomodoro_result.as_deref(),
+                                                    self.session_words_written,
+                                                );
+                                            });
+                                        }
+                                    });
+
+                                    match pomodoro_action {
+                                        Some(true) => {
+                                            let minutes = self.settings.pomodoro_work_minutes.max(1) as u64;
+                                            self.pomodoro = Some(Pomodoro {
+                                                phase: PomodoroPhase::Work,
+                                                ends: std::time::Instant::now() + std::time::Duration::from_secs(minutes * 60),
+                                                words_at_start: self.session_words_written,
+                                            });
+                                            self.pomodoro_result = None;
+                                        }
+                                        Some(false) => self.pomodoro = None,
+                                        None => {}
+                                    }
+
+                                    let mut body_edited = false;
+
+                                    if let Some(format) = format_action {
+                                        edit_text_selection(ctx, body_id, &mut note.body, |text, range| {
+                                            apply_markdown_format(text, range, format)
+                                        });
+                                        body_edited = true;
+                                    }
+
+                                    if ctx.memory(|m| m.has_focus(body_id)) {
+                                        let (outdent, indent) = ctx.input_mut(|i| {
+                                            let outdent = i.consume_shortcut(&OUTDENT);
+                                            (outdent, !outdent && i.consume_shortcut(&INDENT))
+                                        });
+                                        let indent_unit = if self.settings.indent_with_tabs {
+                                            "\t".to_owned()
+                                        } else {
+                                            " ".repeat(self.settings.indent_width)
+                                        };
+
+                                        let continue_list = self.settings.continue_lists
+                                            && egui::text_edit::TextEditState::load(ctx, body_id)
+                                                .and_then(|state| state.cursor.char_range())
+                                                .is_some_and(|r| r.is_empty() && continues_list(&note.body, r.primary.index))
+                                            && ctx.input_mut(|i| !i.modifiers.shift && i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+
+                                        if continue_list {
+                                            edit_text_selection(ctx, body_id, &mut note.body, continue_list_item);
+                                            body_edited = true;
+                                        } else if indent {
+                                            edit_text_selection(ctx, body_id, &mut note.body, |text, range| {
+                                                indent_lines(text, range, &indent_unit)
+                                            });
+                                            body_edited = true;
+                                        } else if outdent {
+                                            let width = self.settings.indent_width;
+                                            edit_text_selection(ctx, body_id, &mut note.body, |text, range| {
+                                                outdent_lines(text, range, width)
+                                            });
+                                            body_edited = true;
+                                        }
+                                    }
+
+                                    if body_edited {
+                                        note.modified = current_unix();
+                                        if self.settings.auto_save {
+                                            self.dirty = true;
+                                        }
+                                    }
+
+                                    let available_height = ui.available_height();
+                                    egui::ScrollArea::vertical()
+                                        .id_salt(("note_body_scroll", note.id))
+                                        .max_height(available_height * 0.7)
+                                        .show(ui, |ui| {
+                                            let previous_len = note.body.chars().count();
+                                            let paper = if self.settings.paper_styles { note.paper } else { PaperStyle::Plain };
+                                            let paper_bg = ui.painter().add(egui::Shape::Noop);
+                                            let mut editor = egui::TextEdit::multiline(&mut note.body)
+                                                .id(body_id)
+                                                .hint_text(self.settings.body_hint.as_str())
+                                                .lock_focus(true)
+                                                .desired_rows(0)
+                                                .desired_width(ui.available_width().min(self.settings.max_editor_width));
+                                            if paper != PaperStyle::Plain {
+                                                editor = editor.background_color(egui::Color32::TRANSPARENT);
+                                            }
+                                            let output = editor.show(ui);
+                                            let base = ui.visuals().text_edit_bg_color();
+                                            let shapes = paper_shapes(ui, paper, output.response.rect, output.galley_pos.y, Some(base));
+                                            ui.painter().set(paper_bg, shapes);
+                                            if output.response.changed() {
+                                                if self.settings.auto_capitalize && note.body.chars().count() == previous_len + 1 {
+                                                    if let Some(cursor) = output.cursor_range {
+                                                        capitalize_typed_char(&mut note.body, cursor.primary.index);
+                                                    }
+                                                }
+                                                note.modified = current_unix();
+                                                if self.settings.auto_save {
+                                                    self.dirty = true;
+                                                }
+                                            }
+                                        });
+                                } else {
+                                    let available_height = ui.available_height();
+                                    egui::ScrollArea::vertical()
+                                        .id_salt(("note_body_scroll", note.id))
+                                        .max_height(available_height * 0.7)
+                                        .show(ui, |ui| {
+                                            ui.set_max_width(ui.available_width().min(self.settings.max_editor_width));
+                                            markdown::show(ui, &note.body);
+                                        });
+                                }
+
+                                ui.separator();
+
+                                let mut save_clicked = false;
+                                let last_modified = note.modified;
+
+                                let dt: DateTime<Local> = Local.timestamp_opt(last_modified as i64, 0).unwrap();
+
+                                ui.horizontal(|ui| {
+                                    ui.label(
+                                        egui::RichText::new(format!("Last modified: {}", dt.format("%d-%m-%Y %H:%M")))
+                                            .size(10.0)
+                                    )
+                                    .on_hover_text(format!(
+                                        "{} ({})",
+                                        dt.format("%A %d %B %Y, %H:%M:%S"),
+                                        relative_time(current_unix().saturating_sub(last_modified))
+                                    ));
+
+                                    if self.settings.show_word_count {
+                                        let word_count = Self::get_word_count(&note.body, self.settings.word_count_mode);
+                                        ui.label(
+                                            egui::RichText::new(format!("Words: {}", word_count))
+                                                .size(10.0)
+                                        );
+                                    }
+
+                                    if !note.tags.is_empty() {
+                                        let tags: Vec<String> = note.tags.iter().map(|t| format!("#{}", t)).collect();
+                                        ui.label(egui::RichText::new(tags.join(" ")).size(10.0));
+                                    }
+
+                                    if expiry_picker(ui, &mut note.expires_at) {
+                                        self.dirty = true;
+                                    }
+                                });
+
+                                let mut attach_clicked = false;
+                                let mut remove_attachment: Option<usize> = None;
+
+                                ui.horizontal_wrapped(|ui| {
+                                    for (i, relative) in note.attachments.iter().enumerate() {
+                                        ui.label(egui::RichText::new(format!("📎 {}", attachment_name(relative))).size(10.0));
+                                        if ui.small_button("Open").clicked() {
+                                            if let Err(e) = open_path(&self.attachments_dir.join(relative)) {
+                                                eprintln!("Failed to open attachment {}: {}", relative, e);
+                                            }
+                                        }
+                                        if ui.small_button("✖").on_hover_text("Remove attachment and delete its file").clicked() {
+                                            remove_attachment = Some(i);
+                                        }
+                                        ui.add_space(8.0);
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    let response = ui.add(
+                                        egui::TextEdit::singleline(&mut self.attach_input)
+                                            .hint_text("Path to a file, or drop one on the window")
+                                            .desired_width(250.0),
+                                    );
+                                    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                    if ui.add_enabled(!self.attach_input.trim().is_empty(), egui::Button::new("Attach file")).clicked()
+                                        || (submitted && !self.attach_input.trim().is_empty())
+                                    {
+                                        attach_clicked = true;
+                                    }
+                                    if let Some(error) = &self.attach_error {
+                                        ui.colored_label(ui.visuals().error_fg_color, error);
+                                    }
+                                });
+
+                                let mut zen_clicked = false;
+                                let mut save_and_new = false;
+                                let mut save_and_close = false;
+                                let mut export_clicked = false;
+                                let note_id = note.id;
+
+                                ui.horizontal(|ui| {
+                                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                        if ui.button("Zen").on_hover_text("Distraction-free writing").clicked() {
+                                            zen_clicked = true;
+                                        }
+                                        if note.editing {
+                                            if ui.button("Save").clicked() {
+                                                note.modified = current_unix();
+                                                note.editing = false;
+                                                save_clicked = true;
+                                                note.backup = None;
+                                            }
+                                            save_and_new = ui
+                                                .button("Save & New")
+                                                .on_hover_text(ctx.format_shortcut(&SAVE_AND_NEW))
+                                                .clicked();
+                                            save_and_close = ui
+                                                .button("Save & Close")
+                                                .on_hover_text(ctx.format_shortcut(&SAVE_AND_CLOSE))
+                                                .clicked();
+                                            if ui.button("Close").clicked() {
+                                                if let Some(original) = &note.backup {
+                                                    note.body = original.clone();
+                                                }
+                                                note.editing = false;
+                                                note.backup = None;
+                                            }
+                                        } else {
+                                            if ui
+                                                .toggle_value(&mut note.locked, "🔒")
+                                                .on_hover_text("Lock this note against edits")
+                                                .changed()
+                                            {
+                                                self.dirty = true;
+                                            }
+                                            let edit = ui.add_enabled(!note.locked, egui::Button::new("Edit"));
+                                            if edit.on_disabled_hover_text("Unlock this note to edit it").clicked() {
+                                                note.start_editing();
+                                            }
+                                            if ui.button("Copy").clicked() {
+                                                ui.ctx().copy_text(note.body.clone());
+                                            }
+                                            if ui.button("Copy link").clicked() {
+                                                ui.ctx().copy_text(note_link(note.id));
+                                            }
+                                            if ui.button("Copy as JSON").clicked() {
+                                                let shared = SharedNote {
+                                                    title: note.title.clone(),
+                                                    body: note.body.clone(),
+                                                    tags: note.tags.clone(),
+                                                };
+                                                match serde_json::to_string_pretty(&shared) {
+                                                    Ok(json) => ui.ctx().copy_text(json),
+                                                    Err(e) => eprintln!("Failed to copy note as JSON: {}", e),
+                                                }
+                                            }
+                                            if ui.button("History").clicked() {
+                                                self.history_note = Some(note.id);
+                                                self.history_preview = None;
+                                            }
+                                            if ui.button("Export…").clicked() {
+                                                export_clicked = true;
+                                            }
+                                            if ui
+                                                .toggle_value(&mut note.no_autosave, "⏸")
+                                                .on_hover_text("Don't auto-save edits to this note; only Save writes them")
+                                                .changed()
+                                            {
+                                                self.dirty = true;
+                                            }
+                                        }
+                                    });
+                                });
+
+                                if save_clicked {
+                                    self.dirty = true;
+                                    self.save_notes();
+                                }
+
+                                if save_and_new {
+                                    self.save_and_new(ctx);
+                                } else if save_and_close {
+                                    self.save_and_close(ctx);
+                                }
+
+                                if zen_clicked {
+                                    self.enter_zen_mode();
+                                }
+
+                                if export_clicked {
+                                    self.open_export_dialog(note_id);
+                                }
+
+                                if attach_clicked {
+                                    let source = std::path::PathBuf::from(self.attach_input.trim());
+                                    self.attach_file(idx, &source);
+                                }
+
+                                if let Some(attachment) = remove_attachment {
+                                    self.remove_attachment(idx, attachment);
+                                }
+                            }
+                        } else {
+                            ui.label("No note selected — create one with New");
+                        }
+                    });
+            }
+        }
+
+        if self.show_shortcuts {
+            self.show_shortcuts_window(ctx);
+        }
+
+        self.show_history_window(ctx);
+        self.show_paste_json_window(ctx);
+        self.show_find_replace_window(ctx);
+        self.show_export_window(ctx);
+        self.show_switch_prompt(ctx);
+        self.show_orphaned_attachments_window(ctx);
+        self.show_lock_prompt(ctx);
+        self.show_data_dir_prompt(ctx);
+        self.show_prune_prompt(ctx);
+        self.show_locked_delete_prompt(ctx);
+        self.show_discard_all_prompt(ctx);
+
+        if let Some(idx) = self.selected.filter(|_| self.current_view == AppView::Notes) {
+            let dropped: Vec<std::path::PathBuf> =
+                ctx.input(|i| i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect());
+            for path in dropped {
+                self.attach_file(idx, &path);
+            }
+        }
+
+        self.save_last_opened();
+        self.track_session_words();
+        self.expire_notes(ctx);
+        self.tick_pomodoro(ctx);
+        self.poll_capture_requests(ctx);
+        self.sync_api_server(ctx);
+        self.handle_api_requests();
+        self.prune_on_close(ctx);
+        self.auto_save(ctx);
+
+        if self.settings.startup_view == StartupView::LastUsed && self.settings.last_view != self.current_view {
+            self.settings.last_view = self.current_view;
+            self.settings_changed = true;
+        }
+
+        self.auto_save_settings(ctx);
+    }
+}
+
+fn parse_search_query(query: &str) -> (Vec<String>, String) {
+    let mut tags = Vec::new();
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        match word.strip_prefix("tag:") {
+            Some(tag) if !tag.is_empty() => tags.push(tag.to_lowercase()),
+            _ => words.push(word.to_lowercase()),
+        }
+    }
+    (tags, words.join(" "))
+}
+
+fn iso_timestamp(unix: u64) -> String {
+    Local.timestamp_opt(unix as i64, 0).unwrap().to_rfc3339()
+}
+
+fn note_front_matter(note: &Note) -> String {
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    let tags: Vec<String> = note.tags.iter().map(|t| quote(t)).collect();
+    format!(
+        "---\ntitle: {}\ncreated: {}\nmodified: {}\ntags: [{}]\n---\n",
+        quote(&note.title),
+        iso_timestamp(note.created),
+        iso_timestamp(note.modified),
+        tags.join(", ")
+    )
+}
+
+fn export_note(note: &Note, format: ExportFormat, front_matter: bool) -> Vec<u8> {
+    if format == ExportFormat::Pdf {
+        return pdf::render(&note.title, &note.body);
+    }
+    let mut out = if front_matter { note_front_matter(note) } else { String::new() };
+    match format {
+        ExportFormat::Markdown => {
+            if !front_matter {
+                out.push_str(&format!("# {}\n\n", note.title));
+            }
+            out.push_str(&note.body);
+        }
+        ExportFormat::Html => {
+            let title = escape_html(&note.title);
+            out.push_str(&format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n<pre>{}</pre>\n</body>\n</html>\n",
+                title,
+                title,
+                escape_html(&note.body)
+            ));
+        }
+        ExportFormat::Pdf | ExportFormat::Bundle => {}
+    }
+    out.into_bytes()
+}
+
+// A bundle holds note.md with front matter plus every attachment under attachments/,
+// with links in the body pointing at the bundled copies.
+fn export_bundle(note: &Note, attachments_dir: &Path) -> std::io::Result<Vec<u8>> {
+    let mut note = note.clone();
+    let mut entries = Vec::new();
+    for relative in &note.attachments {
+        let path = attachments_dir.join(relative);
+        let bundled = format!("{}{}", BUNDLE_ATTACHMENTS, attachment_name(relative));
+        note.body = note.body.replace(&*path.to_string_lossy(), &bundled).replace(relative.as_str(), &bundled);
+        entries.push(zip::Entry { name: bundled, data: fs::read(&path)? });
+    }
+    let markdown = export_note(&note, ExportFormat::Markdown, true);
+    entries.insert(0, zip::Entry { name: BUNDLE_NOTE.to_owned(), data: markdown });
+    zip::write(&entries)
+}
+
+fn import_bundle(path: &Path, attachments_dir: &Path) -> Result<Note, Box<dyn std::error::Error>> {
+    let entries = zip::read(&fs::read(path)?)?;
+    let markdown = entries
+        .iter()
+        .find(|e| e.name == BUNDLE_NOTE)
+        .or_else(|| entries.iter().find(|e| !e.name.contains('/') && e.name.ends_with(".md")))
+        .ok_or("bundle does not contain a note")?;
+
+    let text = normalize_line_endings(&String::from_utf8_lossy(&markdown.data));
+    let mut note = Note::new(rand::random::<u128>());
+    if let Some(stem) = path.file_stem() {
+        note.title = stem.to_string_lossy().to_string();
+    }
+    let (front_matter, body) = parse_front_matter(&text);
+    note.body = body.to_owned();
+    apply_front_matter(&mut note, &front_matter);
+
+    let mut written = Vec::new();
+    let result = entries.iter().try_for_each(|entry| -> Result<(), Box<dyn std::error::Error>> {
+        let Some(name) = entry.name.strip_prefix(BUNDLE_ATTACHMENTS) else {
+            return Ok(());
+        };
+        let file_name = bundle_file_name(name).ok_or_else(|| format!("bundle entry {} has an unsafe name", entry.name))?;
+        let relative = new_attachment_path(attachments_dir, note.id, file_name)?;
+        fs::write(attachments_dir.join(&relative), &entry.data)?;
+        written.push(attachments_dir.join(&relative));
+        note.body = note.body.replace(&entry.name, &relative);
+        note.attachments.push(relative);
+        Ok(())
+    });
+    if let Err(e) = result {
+        for path in &written {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_dir(attachments_dir.join(format!("{:032x}", note.id)));
+        return Err(e);
+    }
+    Ok(note)
+}
+
+// Bundled attachments have to be a single plain file name on every platform, so an
+// entry can't climb out of the note's attachment folder.
+fn bundle_file_name(name: &str) -> Option<&str> {
+    if name.contains(['/', '\\', ':']) {
+        return None;
+    }
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) => Some(name),
+        _ => None,
+    }
+}
+
+fn template_combo(ui: &mut egui::Ui, id: &str, selected: &mut String, templates: &[NoteTemplate]) -> bool {
+    let mut changed = false;
+    let current = if selected.is_empty() { "Blank".to_owned() } else { selected.clone() };
+    egui::ComboBox::from_id_salt(id).selected_text(current).show_ui(ui, |ui| {
+        if ui.selectable_label(selected.is_empty(), "Blank").clicked() {
+            selected.clear();
+            changed = true;
+        }
+        for template in templates {
+            if ui.selectable_label(*selected == template.name, &template.name).clicked() {
+                *selected = template.name.clone();
+                changed = true;
+            }
+        }
+    });
+    changed
+}
+
+fn default_report_path() -> String {
+    let mut path = dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    path.push("notes-report.md");
+    path.to_string_lossy().to_string()
+}
+
+fn stats_report(notes: &[Note], word_count_mode: WordCountMode) -> String {
+    let date = |unix: u64| Local.timestamp_opt(unix as i64, 0).unwrap();
+    let total_words: usize = notes.iter().map(|n| NotesApp::get_word_count(&n.body, word_count_mode)).sum();
+
+    let mut report = format!("# Notes report\n\nGenerated {}\n\n", Local::now().format("%d-%m-%Y %H:%M"));
+    report.push_str(&format!("- Total notes: {}\n- Total words: {}\n", notes.len(), total_words));
+    if let Some(latest) = notes.iter().max_by_key(|n| n.modified) {
+        report.push_str(&format!(
+            "- Most recently modified: {} ({})\n",
+            latest.title,
+            date(latest.modified).format("%d-%m-%Y %H:%M")
+        ));
+    }
+    if let Some(oldest) = notes.iter().min_by_key(|n| n.created) {
+        report.push_str(&format!("- Oldest note: {} ({})\n", oldest.title, date(oldest.created).format("%d-%m-%Y")));
+    }
+
+    let mut by_tag: std::collections::BTreeMap<&str, usize> = std::collections::BTreeMap::new();
+    for note in notes {
+        if note.tags.is_empty() {
+            *by_tag.entry("(untagged)").or_default() += 1;
+        }
+        for tag in &note.tags {
+            *by_tag.entry(tag).or_default() += 1;
+        }
+    }
+    if !by_tag.is_empty() {
+        report.push_str("\n## Notes by tag\n\n| Tag | Notes |\n|---|---:|\n");
+        for (tag, count) in &by_tag {
+            report.push_str(&format!("| {} | {} |\n", tag, count));
+        }
+    }
+
+    let mut by_month: std::collections::BTreeMap<String, usize> = std::collections::BTreeMap::new();
+    for note in notes {
+        *by_month.entry(date(note.created).format("%Y-%m").to_string()).or_default() += 1;
+    }
+    if let Some(&max) = by_month.values().max() {
+        report.push_str("\n## Notes created per month\n\n```\n");
+        for (month, count) in &by_month {
+            let bar = "█".repeat((count * 30).div_ceil(max));
+            report.push_str(&format!("{} {:>4} {}\n", month, count, bar));
+        }
+        report.push_str("```\n");
+    }
+    report
+}
+
+fn all_notes_markdown(notes: &[Note]) -> String {
+    notes
+        .iter()
+        .filter(|n| !n.archived)
+        .map(|n| format!("# {}\n\n{}", n.title, n.body.trim_end()))
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn export_file_name(template: &str, note: &Note) -> String {
+    let expanded = template
+        .replace("{title}", &note.title)
+        .replace("{date}", &Local::now().format("%Y-%m-%d").to_string())
+        .replace("{id}", &format!("{:032x}", note.id));
+    let name: String = expanded
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
+        .take(200)
+        .collect();
+    let name = name.trim();
+    // Windows refuses its device names even with an extension added.
+    let upper = name.to_ascii_uppercase();
+    let reserved = ["CON", "PRN", "AUX", "NUL"].contains(&upper.as_str())
+        || (upper.len() == 4
+            && (upper.starts_with("COM") || upper.starts_with("LPT"))
+            && upper.ends_with(|c: char| ('1'..='9').contains(&c)));
+    if name.is_empty() {
+        "note".to_owned()
+    } else if reserved {
+        format!("{}_", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+fn copy_attachment(dir: &std::path::Path, note_id: u128, source: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
+    let file_name = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("path does not name a file")?;
+    let relative = new_attachment_path(dir, note_id, file_name)?;
+    fs::copy(source, dir.join(&relative))?;
+    Ok(relative)
+}
+
+fn new_attachment_path(dir: &std::path::Path, note_id: u128, file_name: &str) -> std::io::Result<String> {
+    let folder = format!("{:032x}", note_id);
+    fs::create_dir_all(dir.join(&folder))?;
+
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (file_name, None),
+    };
+    let mut relative = format!("{}/{}", folder, file_name);
+    let mut n = 2;
+    while dir.join(&relative).exists() {
+        relative = match extension {
+            Some(extension) => format!("{}/{} ({}).{}", folder, stem, n, extension),
+            None => format!("{}/{} ({})", folder, stem, n),
+        };
+        n += 1;
+    }
+    Ok(relative)
+}
+
+fn start_of_day(date: chrono::NaiveDate) -> u64 {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|dt| dt.and_local_timezone(Local).earliest())
+        .map_or(0, |dt| dt.timestamp().max(0) as u64)
+}
+
+// Notes expire at the start of the chosen day and are archived from then on.
+fn expiry_picker(ui: &mut egui::Ui, expires_at: &mut Option<u64>) -> bool {
+    let mut changed = false;
+    let label = match *expires_at {
+        Some(t) => format!("⌛ Expires {}", Local.timestamp_opt(t as i64, 0).unwrap().format("%d-%m-%Y")),
+        None => "⌛ No expiry".to_owned(),
+    };
+    ui.menu_button(egui::RichText::new(label).size(10.0), |ui| {
+        let today = Local::now().date_naive();
+        let date = expires_at
+            .and_then(|t| Local.timestamp_opt(t as i64, 0).single())
+            .map_or(today + chrono::Days::new(1), |dt| dt.date_naive());
+        let (mut day, mut month, mut year) = (date.day(), date.month(), date.year());
+        let edited = ui
+            .horizontal(|ui| {
+                ui.label("Archive on:");
+                let day = ui.add(egui::DragValue::new(&mut day).range(1..=31)).changed();
+                let month = ui.add(egui::DragValue::new(&mut month).range(1..=12)).changed();
+                let year = ui.add(egui::DragValue::new(&mut year).range(today.year()..=9999)).changed();
+                day || month || year
+            })
+            .inner;
+        if edited {
+            // Clamp the day so moving from the 31st into a shorter month still gives a date.
+            if let Some(date) = (1..=day).rev().find_map(|d| chrono::NaiveDate::from_ymd_opt(year, month, d)) {
+                *expires_at = Some(start_of_day(date));
+                changed = true;
+            }
+        }
+        ui.separator();
+        for (label, days) in [("Tomorrow", 1), ("In a week", 7), ("In a month", 30)] {
+            if ui.button(label).clicked() {
+                *expires_at = Some(start_of_day(today + chrono::Days::new(days)));
+                changed = true;
+                ui.close();
+            }
+        }
+        if ui.add_enabled(expires_at.is_some(), egui::Button::new("Never")).clicked() {
+            *expires_at = None;
+            changed = true;
+            ui.close();
+        }
+    });
+    changed
+}
+
+// Doubles after each failed save, starting at SAVE_RETRY_DELAY and capped by the setting.
+fn save_retry_delay(attempts: u32, max_seconds: u32) -> std::time::Duration {
+    let delay = SAVE_RETRY_DELAY * 2f64.powi(attempts.saturating_sub(1).min(16) as i32);
+    std::time::Duration::from_secs_f64(delay.min(max_seconds as f64))
+}
+
+fn attachment_name(relative: &str) -> &str {
+    relative.rsplit('/').next().unwrap_or(relative)
+}
+
+// Returns Some(true) to start a session and Some(false) to stop the running one.
+fn pomodoro_widget(ui: &mut egui::Ui, pomodoro: Option<&Pomodoro>, result: Option<&str>, words: usize) -> Option<bool> {
+    let Some(pomodoro) = pomodoro else {
+        let start = ui.button("🍅").on_hover_text("Start a writing session").clicked();
+        if let Some(result) = result {
+            ui.label(egui::RichText::new(result).size(10.0).weak());
+        }
+        return start.then_some(true);
+    };
+    let left = pomodoro.ends.saturating_duration_since(std::time::Instant::now()).as_secs();
+    let label = match pomodoro.phase {
+        PomodoroPhase::Work => {
+            let written = words.saturating_sub(pomodoro.words_at_start);
+            format!("🍅 {:02}:{:02} · {} words", left / 60, left % 60, written)
+        }
+        PomodoroPhase::Break => format!("☕ {:02}:{:02}", left / 60, left % 60),
+    };
+    ui.label(egui::RichText::new(label).monospace());
+    ui.small_button("Stop").clicked().then_some(false)
+}
+
+fn notify_desktop(title: &str, message: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        // No notification tool ships with Windows; the taskbar flash has to do.
+        let _ = (title, message);
+        Ok(())
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!("display notification \"{}\" with title \"{}\"", quote(message), quote(title));
+        std::process::Command::new("osascript").args(["-e", &script]).spawn().map(|_| ())
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        std::process::Command::new("notify-send").args([title, message]).spawn().map(|_| ())
+    }
+}
+
+fn open_path(path: &std::path::Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+
+    command.arg(path).spawn().map(|_| ())
+}
+
+fn parse_front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (Vec::new(), text);
+    };
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end();
+        if line == "---" {
+            return (fields, rest[offset..].trim_start_matches(['\r', '\n']));
+        }
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if let Some((_, value)) = fields.last_mut() {
+                if !value.is_empty() {
+                    value.push_str(", ");
+                }
+                value.push_str(item.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    (Vec::new(), text)
+}
+
+fn note_from_file(path: &Path) -> std::io::Result<Note> {
+    let text = normalize_line_endings(&fs::read_to_string(path)?);
+    let mut note = Note::new(rand::random::<u128>());
+    if let Some(stem) = path.file_stem() {
+        note.title = stem.to_string_lossy().to_string();
+    }
+    let (front_matter, body) = parse_front_matter(&text);
+    note.body = body.to_owned();
+    apply_front_matter(&mut note, &front_matter);
+    Ok(note)
+}
+
+fn import_folder(root: &Path) -> std::io::Result<(Vec<Note>, Vec<std::path::PathBuf>)> {
+    let mut notes = Vec::new();
+    let mut skipped = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries: Vec<std::path::PathBuf> = fs::read_dir(&dir)?.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        entries.sort();
+        for path in entries {
+            if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let importable = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ["md", "markdown", "txt"].contains(&ext.to_lowercase().as_str()));
+            if !importable {
+                skipped.push(path);
+                continue;
+            }
+            match note_from_file(&path) {
+                Ok(mut note) => {
+                    let folders = path.parent().and_then(|p| p.strip_prefix(root).ok()).into_iter().flat_map(Path::components);
+                    for folder in folders {
+                        let tag = folder.as_os_str().to_string_lossy().to_string();
+                        if !note.tags.contains(&tag) {
+                            note.tags.push(tag);
+                        }
+                    }
+                    notes.push(note);
+                }
+                Err(e) => {
+                    eprintln!("Failed to import {}: {}", path.display(), e);
+                    skipped.push(path);
+                }
+            }
+        }
+    }
+    Ok((notes, skipped))
+}
+
+fn apply_front_matter(note: &mut Note, fields: &[(String, String)]) {
+    let unquote = |value: &str| -> String {
+        if value.starts_with('"') {
+            serde_json::from_str(value).unwrap_or_else(|_| value.trim_matches('"').to_owned())
+        } else {
+            value.trim_matches('\'').to_owned()
+        }
+    };
+    let timestamp = |value: &str| DateTime::parse_from_rfc3339(&unquote(value)).ok().map(|dt| dt.timestamp().max(0) as u64);
+
+    for (key, value) in fields {
+        match key.as_str() {
+            "title" if !value.is_empty() => note.title = unquote(value),
+            "created" => note.created = timestamp(value).unwrap_or(note.created),
+            "modified" => note.modified = timestamp(value).unwrap_or(note.modified),
+            "tags" => {
+                if let Ok(tags) = serde_json::from_str::<Vec<String>>(value) {
+                    note.tags = tags;
+                    continue;
+                }
+                note.tags = value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(|t| unquote(t.trim()))
+                    .filter(|t| !t.is_empty())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F)
+}
+
+fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c as u32, 0x3000..=0x303F | 0xFF00..=0xFF0F | 0xFF1A..=0xFF20)
+}
+
+fn body_preview(body: &str, max_chars: usize) -> String {
+    let collapsed = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > max_chars {
+        let truncated: String = collapsed.chars().take(max_chars).collect();
+        format!("{}…", truncated.trim_end())
+    } else {
+        collapsed
+    }
+}
+
+fn truncate_title(title: &str, max_chars: usize) -> String {
+    if max_chars == 0 || title.chars().count() <= max_chars {
+        return title.to_owned();
+    }
+    let truncated: String = title.chars().take(max_chars).collect();
+    format!("{}…", truncated.trim_end())
+}
+
+// Keeps a Markdown hard line break (two trailing spaces) on lines with text, and
+// collapses trailing blank lines down to a single final newline.
+fn trim_trailing_whitespace(text: &str) -> String {
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            let trimmed = line.trim_end();
+            if !trimmed.is_empty() && line.ends_with("  ") {
+                format!("{}  ", trimmed)
+            } else {
+                trimmed.to_owned()
+            }
+        })
+        .collect();
+    let body = lines.join("\n");
+    let content = body.trim_end_matches('\n');
+    if content.len() < body.len() && !content.is_empty() {
+        format!("{}\n", content)
+    } else {
+        content.to_owned()
+    }
+}
+
+fn find_matches(text: &str, needle: &str, case_sensitive: bool) -> Vec<std::ops::Range<usize>> {
+    let mut matches = Vec::new();
+    if needle.is_empty() {
+        return matches;
+    }
+    let mut start = 0;
+    while start < text.len() {
+        let mut haystack = text[start..].chars();
+        let mut end = start;
+        let found = needle.chars().all(|n| match haystack.next() {
+            Some(c) => {
+                end += c.len_utf8();
+                if case_sensitive { c == n } else { c.to_lowercase().eq(n.to_lowercase()) }
+            }
+            None => false,
+        });
+        if found {
+            matches.push(start..end);
+            start = end;
+        } else {
+            start += text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    matches
+}
+
+fn replace_matches(text: &str, needle: &str, replacement: &str, case_sensitive: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in find_matches(text, needle, case_sensitive) {
+        result.push_str(&text[last..range.start]);
+        result.push_str(replacement);
+        last = range.end;
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+fn match_preview(text: &str, needle: &str, case_sensitive: bool) -> String {
+    let Some(range) = find_matches(text, needle, case_sensitive).into_iter().next() else {
+        return String::new();
+    };
+    let before: String = text[..range.start].chars().rev().take(30).collect::<Vec<_>>().into_iter().rev().collect();
+    let after: String = text[range.end..].chars().take(30).collect();
+    format!("…{}{}{}…", before, &text[range], after).replace('\n', " ")
+}
+
+fn capitalize_typed_char(text: &mut String, cursor: usize) {
+    if cursor == 0 {
+        return;
+    }
+    let start = byte_index(text, cursor - 1);
+    let Some(typed) = text[start..].chars().next() else {
+        return;
+    };
+    let mut upper = typed.to_uppercase();
+    let (Some(capital), None) = (upper.next(), upper.next()) else {
+        return;
+    };
+    if !typed.is_lowercase() {
+        return;
+    }
+
+    let before = &text[..start];
+    let trimmed = before.trim_end();
+    let sentence_start = trimmed.is_empty() || (trimmed.len() < before.len() && trimmed.ends_with(['.', '!', '?']));
+    if sentence_start {
+        text.replace_range(start..start + typed.len_utf8(), capital.encode_utf8(&mut [0; 4]));
+    }
+}
+
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices().nth(char_index).map(|(i, _)| i).unwrap_or(text.len())
+}
+
+fn apply_markdown_format(text: &mut String, range: std::ops::Range<usize>, format: MarkdownFormat) -> std::ops::Range<usize> {
+    match format {
+        MarkdownFormat::Bold | MarkdownFormat::Italic => {
+            let marker = if matches!(format, MarkdownFormat::Bold) { "**" } else { "*" };
+            let marker_len = marker.chars().count();
+            text.insert_str(byte_index(text, range.end), marker);
+            text.insert_str(byte_index(text, range.start), marker);
+            (range.start + marker_len)..(range.end + marker_len)
+        }
+        MarkdownFormat::Heading => prefix_lines(text, range, "# "),
+        MarkdownFormat::List => prefix_lines(text, range, "- "),
+    }
+}
+
+fn line_starts_in_range(text: &str, range: &std::ops::Range<usize>) -> Vec<usize> {
+    let mut line_starts = vec![0];
+    for (i, c) in text.chars().enumerate() {
+        if c == '\n' && i < range.end {
+            line_starts.push(i + 1);
+        }
+    }
+    let first_line = line_starts.iter().rposition(|&start| start <= range.start).unwrap_or(0);
+    line_starts.split_off(first_line)
+}
+
+fn prefix_lines(text: &mut String, range: std::ops::Range<usize>, prefix: &str) -> std::ops::Range<usize> {
+    let prefix_len = prefix.chars().count();
+    let line_starts = line_starts_in_range(text, &range);
+
+    for &start in line_starts.iter().rev() {
+        text.insert_str(byte_index(text, start), prefix);
+    }
+    (range.start + prefix_len)..(range.end + prefix_len * line_starts.len())
+}
+
+fn indent_lines(text: &mut String, range: std::ops::Range<usize>, indent: &str) -> std::ops::Range<usize> {
+    if line_starts_in_range(text, &range).len() > 1 {
+        return prefix_lines(text, range, indent);
+    }
+    let indent_len = indent.chars().count();
+    text.insert_str(byte_index(text, range.start), indent);
+    (range.start + indent_len)..(range.end + indent_len)
+}
+
+fn outdent_lines(text: &mut String, range: std::ops::Range<usize>, width: usize) -> std::ops::Range<usize> {
+    let line_starts = line_starts_in_range(text, &range);
+    let mut removed_first = 0;
+    let mut removed_total = 0;
+
+    for (line, &start) in line_starts.iter().enumerate().rev() {
+        let removable = if text.chars().nth(start) == Some('\t') {
+            1
+        } else {
+            text.chars().skip(start).take(width).take_while(|&c| c == ' ').count()
+        };
+        let from = byte_index(text, start);
+        let to = byte_index(text, start + removable);
+        text.replace_range(from..to, "");
+        removed_total += removable;
+        if line == 0 {
+            removed_first = removable;
+        }
+    }
+
+    let first_start = line_starts[0];
+    let start = range.start.saturating_sub(removed_first).max(first_start);
+    let end = range.end.saturating_sub(removed_total).max(start);
+    start..end
+}
+
+fn list_item_prefix(line: &str) -> Option<(usize, String)> {
+    let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let rest = &line[indent_len..];
+    let (marker_len, next_marker) = if rest.starts_with("- ") || rest.starts_with("* ") {
+        (2, rest[..2].to_owned())
+    } else {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        let number: u64 = rest[..digits].parse().ok()?;
+        if !rest[digits..].starts_with(". ") {
+            return None;
+        }
+        (digits + 2, format!("{}. ", number + 1))
+    };
+    Some((indent_len + marker_len, format!("{}{}", &line[..indent_len], next_marker)))
+}
+
+fn line_around(text: &str, cursor: usize) -> std::ops::Range<usize> {
+    let start = text[..cursor].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[cursor..].find('\n').map_or(text.len(), |i| cursor + i);
+    start..end
+}
+
+fn continues_list(text: &str, cursor: usize) -> bool {
+    let cursor = byte_index(text, cursor);
+    let line = line_around(text, cursor);
+    list_item_prefix(&text[line.clone()]).is_some_and(|(prefix_len, _)| cursor >= line.start + prefix_len)
+}
+
+fn continue_list_item(text: &mut String, range: std::ops::Range<usize>) -> std::ops::Range<usize> {
+    let cursor = byte_index(text, range.end);
+    let line = line_around(text, cursor);
+    let Some((prefix_len, next_prefix)) = list_item_prefix(&text[line.clone()]) else {
+        return range;
+    };
+
+    if text[line.start + prefix_len..line.end].trim().is_empty() {
+        text.replace_range(line.clone(), "");
+        let start = text[..line.start].chars().count();
+        return start..start;
+    }
+
+    let insert = format!("\n{}", next_prefix);
+    text.insert_str(cursor, &insert);
+    let end = range.end + insert.chars().count();
+    end..end
+}
+
+fn edit_text_selection(
+    ctx: &egui::Context,
+    id: egui::Id,
+    text: &mut String,
+    edit: impl FnOnce(&mut String, std::ops::Range<usize>) -> std::ops::Range<usize>,
+) {
+    let mut state = egui::text_edit::TextEditState::load(ctx, id).unwrap_or_default();
+    let end = text.chars().count();
+    let range = state
+        .cursor
+        .char_range()
+        .map(|r| r.as_sorted_char_range())
+        .unwrap_or(end..end);
+    let range = edit(text, range);
+    state.cursor.set_char_range(Some(egui::text::CCursorRange::two(
+        egui::text::CCursor::new(range.start),
+        egui::text::CCursor::new(range.end),
+    )));
+    state.store(ctx, id);
+    ctx.memory_mut(|m| m.request_focus(id));
+}
+
+fn load_notes<P: AsRef<Path>>(path: P) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+    if !path.as_ref().exists() {
+        return Ok(vec![]);
+    }
+    let data = fs::read_to_string(path)?;
+    let value: serde_json::Value = serde_json::from_str(&data)?;
+    migrate_notes(value)
+}
+
+fn is_default_title(title: &str) -> bool {
+    title == "Untitled"
+        || title
+            .strip_prefix("Note ")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn migrate_notes(value: serde_json::Value) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+    let (version, mut notes) = match value {
+        serde_json::Value::Array(_) => (0, value),
+        serde_json::Value::Object(mut file) => {
+            let version = file.get("version").and_then(serde_json::Value::as_u64).unwrap_or(0);
+            let notes = file.remove("notes").unwrap_or_else(|| serde_json::Value::Array(Vec::new()));
+            (version, notes)
+        }
+        _ => return Err("notes file is neither a list of notes nor a versioned notes file".into()),
+    };
+
+    if version > NOTES_SCHEMA_VERSION as u64 {
+        return Err(format!("notes file version {} is newer than supported version {}", version, NOTES_SCHEMA_VERSION).into());
+    }
+
+    let note_objects = notes.as_array_mut().into_iter().flatten().filter_map(|n| n.as_object_mut());
+    for note in note_objects {
+        if version < 1 {
+            note.entry("tags").or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        }
+        if version < 2 {
+            let modified = note.get("modified").cloned().unwrap_or(serde_json::Value::from(0));
+            note.entry("created").or_insert(modified);
+        }
+        if version < 3 {
+            note.entry("no_autosave").or_insert(serde_json::Value::Bool(false));
+        }
+        if version < 4 {
+            note.entry("pinned").or_insert(serde_json::Value::Bool(false));
+            note.entry("archived").or_insert(serde_json::Value::Bool(false));
+        }
+        if version < 5 {
+            note.entry("attachments").or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        }
+        if version < 6 {
+            note.entry("icon").or_insert(serde_json::Value::Null);
+        }
+        if version < 7 {
+            if let Some(serde_json::Value::String(body)) = note.get_mut("body") {
+                *body = normalize_line_endings(body);
+            }
+        }
+        if version < 8 {
+            note.entry("last_opened").or_insert(serde_json::Value::from(0));
+        }
+        if version < 9 {
+            note.entry("pinned_in").or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        }
+        if version < 10 {
+            note.entry("locked").or_insert(serde_json::Value::Bool(false));
+        }
+        if version < 11 {
+            note.entry("paper").or_insert(serde_json::Value::from("Plain"));
+        }
+        if version < 12 {
+            note.entry("expires_at").or_insert(serde_json::Value::Null);
+        }
+    }
+
+    Ok(serde_json::from_value(notes)?)
+}
+
+impl NoteStore {
+    fn new(dir: std::path::PathBuf) -> Self {
+        Self { dir, written: std::collections::HashMap::new(), order: Vec::new(), compact: false, skipped: Vec::new() }
+    }
+
+    fn skipped_files(&self) -> Vec<String> {
+        self.skipped.iter().map(|(id, error)| format!("{:032x}.json: {}", id, error)).collect()
+    }
+
+    fn index_path(&self) -> std::path::PathBuf {
+        self.dir.join("index.json")
+    }
+
+    fn note_path(&self, id: u128) -> std::path::PathBuf {
+        self.dir.join(format!("{:032x}.json", id))
+    }
+
+    fn load_or_migrate<P: AsRef<Path>>(&mut self, legacy_path: P) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+        let legacy_path = legacy_path.as_ref();
+        if self.index_path().exists() || !legacy_path.exists() {
+            return self.load();
+        }
+
+        let notes = load_notes(legacy_path)?;
+        self.save(&notes)?;
+        fs::rename(legacy_path, legacy_path.with_extension("json.bak"))?;
+        Ok(notes)
+    }
+
+    fn load(&mut self) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+        let index: NotesIndex = match fs::read_to_string(self.index_path()) {
+            Ok(data) => serde_json::from_str(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if index.version > NOTES_SCHEMA_VERSION {
+            return Err(format!("notes index version {} is newer than supported version {}", index.version, NOTES_SCHEMA_VERSION).into());
+        }
+
+        self.skipped.clear();
+        let mut files = std::collections::HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|_| path.extension().is_some_and(|ext| ext == "json"))
+                .and_then(|stem| u128::from_str_radix(stem, 16).ok())
+            else {
+                continue;
+            };
+            match fs::read_to_string(&path) {
+                Ok(data) => {
+                    files.insert(id, data);
+                }
+                Err(e) => self.skipped.push((id, e.to_string())),
+            }
+        }
+
+        let mut ids: Vec<u128> = index.order.iter().copied().filter(|id| files.contains_key(id)).collect();
+        let mut strays: Vec<u128> = files.keys().copied().filter(|id| !index.order.contains(id)).collect();
+        strays.sort();
+        ids.extend(strays);
+
+        let mut notes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let note = serde_json::from_str::<serde_json::Value>(&files[&id])
+                .map_err(|e| e.into())
+                .and_then(|value| migrate_notes(serde_json::json!({ "version": index.version, "notes": [value] })));
+            match note {
+                Ok(mut migrated) => notes.append(&mut migrated),
+                Err(e) => {
+                    files.remove(&id);
+                    self.skipped.push((id, e.to_string()));
+                }
+            }
+        }
+        self.skipped.sort_by_key(|(id, _)| index.order.iter().position(|i| i == id).unwrap_or(usize::MAX));
+
+        self.written = files;
+        if index.version == NOTES_SCHEMA_VERSION {
+            self.order = index.order;
+        }
+        Ok(notes)
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<BodyChanges, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut changed = Vec::new();
+        for note in notes {
+            let json = if self.compact { serde_json::to_string(note)? } else { serde_json::to_string_pretty(note)? };
+            if self.written.get(&note.id) != Some(&json) {
+                fs::write(self.note_path(note.id), &json)?;
+                let previous = self.written.insert(note.id, json).and_then(|old| serde_json::from_str::<Note>(&old).ok());
+                if previous.as_ref().is_none_or(|old| old.body != note.body) {
+                    let version = previous.map(|old| NoteVersion { saved: old.modified, body: old.body });
+                    changed.push((note.id, version));
+                }
+            }
+        }
+
+        let mut order: Vec<u128> = notes.iter().map(|n| n.id).collect();
+        for (id, _) in &self.skipped {
+            if !order.contains(id) {
+                order.push(*id);
+            }
+        }
+        let removed: Vec<u128> = self.written.keys().copied().filter(|id| !order.contains(id)).collect();
+        for id in removed {
+            match fs::remove_file(self.note_path(id)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            self.written.remove(&id);
+        }
+
+        if order != self.order || !self.index_path().exists() {
+            let index = NotesIndex { version: NOTES_SCHEMA_VERSION, order };
+            let json = if self.compact { serde_json::to_string(&index)? } else { serde_json::to_string_pretty(&index)? };
+            fs::write(self.index_path(), json)?;
+            self.order = index.order;
+        }
+        Ok(changed)
+    }
+
+    // Whether saving `notes` would change anything on disk.
+    fn differs_from(&self, notes: &[Note]) -> bool {
+        let saved_order = self.order.iter().filter(|id| !self.skipped.iter().any(|(skipped, _)| skipped == *id));
+        if !notes.iter().map(|n| &n.id).eq(saved_order) {
+            return true;
+        }
+        notes.iter().any(|note| {
+            let saved = self.written.get(&note.id).and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok());
+            saved.is_none() || saved != serde_json::to_value(note).ok()
+        })
+    }
+
+    // Updates last_opened in note files that are already saved, leaving the rest of
+    // each file as it was last written.
+    fn save_last_opened(&mut self, notes: &[Note]) -> Result<(), Box<dyn std::error::Error>> {
+        for note in notes {
+            let Some(mut saved) = self.written.get(&note.id).and_then(|json| serde_json::from_str::<Note>(json).ok()) else {
+                continue;
+            };
+            if saved.last_opened == note.last_opened {
+                continue;
+            }
+            saved.last_opened = note.last_opened;
+            let json = if self.compact { serde_json::to_string(&saved)? } else { serde_json::to_string_pretty(&saved)? };
+            fs::write(self.note_path(note.id), &json)?;
+            self.written.insert(note.id, json);
+        }
+        Ok(())
+    }
+}
+
+impl InstanceLock {
+    // Fails with the holder's process id, or None when the lock file doesn't name one yet.
+    fn acquire(path: std::path::PathBuf) -> Result<Self, Option<u32>> {
+        for _ in 0..2 {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    use std::io::Write;
+                    if let Err(e) = write!(file, "{}", std::process::id()) {
+                        eprintln!("Failed to write lock file: {}", e);
+                    }
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => match Self::holder(&path) {
+                    Some(pid) if !process_running(pid) => {
+                        let _ = fs::remove_file(&path);
+                    }
+                    holder => return Err(holder),
+                },
+                Err(e) => {
+                    eprintln!("Failed to create lock file: {}", e);
+                    return Ok(Self { path });
+                }
+            }
+        }
+        Ok(Self { path })
+    }
+
+    // An empty or garbled lock file may belong to an instance that hasn't written its
+    // id yet, so it counts as held; "Open anyway" takes it over.
+    fn holder(path: &Path) -> Option<u32> {
+        fs::read_to_string(path).ok().and_then(|s| s.trim().parse::<u32>().ok())
+    }
+
+    fn take_over(path: std::path::PathBuf) -> Self {
+        if let Err(e) = fs::write(&path, std::process::id().to_string()) {
+            eprintln!("Failed to write lock file: {}", e);
+        }
+        Self { path }
+    }
+}
 
-                                ui.separator();
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let owned = fs::read_to_string(&self.path).is_ok_and(|s| s.trim() == std::process::id().to_string());
+        if owned {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
 
-                                let mut save_clicked = false;
-                                let last_modified = note.modified;
+// Monitor rectangles in physical pixels, or None where they can't be listed. Wayland ignores
+// window positions and macOS keeps windows on a screen itself, so neither needs them.
+#[cfg(target_os = "windows")]
+fn monitor_rects() -> Option<Vec<egui::Rect>> {
+    #[repr(C)]
+    struct Rect {
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+    }
+    type MonitorEnumProc = unsafe extern "system" fn(isize, isize, *mut Rect, isize) -> i32;
+    #[link(name = "user32")]
+    extern "system" {
+        fn EnumDisplayMonitors(hdc: isize, clip: *const Rect, callback: MonitorEnumProc, data: isize) -> i32;
+    }
+    unsafe extern "system" fn collect(_monitor: isize, _hdc: isize, rect: *mut Rect, data: isize) -> i32 {
+        let (rects, rect) = unsafe { (&mut *(data as *mut Vec<egui::Rect>), &*rect) };
+        rects.push(egui::Rect::from_min_max(
+            egui::pos2(rect.left as f32, rect.top as f32),
+            egui::pos2(rect.right as f32, rect.bottom as f32),
+        ));
+        1
+    }
 
-                                let dt: DateTime<Local> = Local.timestamp_opt(last_modified as i64, 0).unwrap();
+    let mut rects: Vec<egui::Rect> = Vec::new();
+    let ok = unsafe { EnumDisplayMonitors(0, std::ptr::null(), collect, &mut rects as *mut Vec<egui::Rect> as isize) };
+    (ok != 0 && !rects.is_empty()).then_some(rects)
+}
 
-                                ui.horizontal(|ui| {
-                                    ui.label(
-                                        egui::RichText::new(format!("Last modified: {}", dt.format("%d-%m-%Y %H:%M")))
-                                            .size(10.0)
-                                    );
+#[cfg(target_os = "linux")]
+fn monitor_rects() -> Option<Vec<egui::Rect>> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return None;
+    }
+    let output = std::process::Command::new("xrandr").arg("--listmonitors").output().ok()?;
+    let rects: Vec<egui::Rect> = String::from_utf8_lossy(&output.stdout).lines().filter_map(parse_xrandr_monitor).collect();
+    (!rects.is_empty()).then_some(rects)
+}
 
-                                    if self.settings.show_word_count {
-                                        let word_count = Self::get_word_count(&note.body);
-                                        ui.label(
-                                            egui::RichText::new(format!("Words: {}", word_count))
-                                                .size(10.0)
-                                        );
-                                    }
-                                });
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn monitor_rects() -> Option<Vec<egui::Rect>> {
+    None
+}
 
-                                ui.horizontal(|ui| {
-                                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                                        if note.editing {
-                                            if ui.button("Save").clicked() {
-                                                note.modified = current_unix();
-                                                note.editing = false;
-                                                save_clicked = true;
-                                                note.backup = None;
-                                            }
-                                            if ui.button("Close").clicked() {
-                                                if let Some(original) = &note.backup {
-                                                    note.body = original.clone();
-                                                }
-                                                note.editing = false;
-                                                note.backup = None;
-                                            }
-                                        } else {
-                                            if ui.button("Edit").clicked() {
-                                                note.backup = Some(note.body.clone());
-                                                note.editing = true;
-                                            }
-                                            if ui.button("Copy").clicked() {
-                                                ui.ctx().copy_text(note.body.clone());
-                                            }
-                                        }
-                                    });
-                                });
+// Parses a line of `xrandr --listmonitors`, e.g. " 1: +HDMI-1 2560/597x1440/336+1920+0  HDMI-1".
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_xrandr_monitor(line: &str) -> Option<egui::Rect> {
+    let geometry = line.split_whitespace().nth(2)?;
+    let (size, offset) = geometry.split_once('+')?;
+    let (width, height) = size.split_once('x')?;
+    let (x, y) = offset.split_once('+')?;
+    let number = |field: &str| field.split('/').next()?.parse::<f32>().ok();
+    Some(egui::Rect::from_min_size(
+        egui::pos2(number(x)?, number(y)?),
+        egui::vec2(number(width)?, number(height)?),
+    ))
+}
 
-                                if save_clicked {
-                                    self.dirty = true;
-                                    self.save_notes();
-                                }
-                            }
-                        } else {
-                            ui.label("No note selected — create one with New");
-                        }
-                    });
-            }
-        }
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
 
-        if self.dirty && self.settings.auto_save {
-            self.save_notes();
-        }
+#[cfg(target_os = "windows")]
+fn process_running(pid: u32) -> bool {
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> isize;
+        fn GetExitCodeProcess(process: isize, code: *mut u32) -> i32;
+        fn CloseHandle(handle: isize) -> i32;
+    }
 
-        if self.settings_changed {
-            self.save_settings();
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if process == 0 {
+        // Access denied still means the process exists.
+        return std::io::Error::last_os_error().raw_os_error() == Some(5);
+    }
+    let mut code = 0;
+    let ok = unsafe { GetExitCodeProcess(process, &mut code) };
+    unsafe { CloseHandle(process) };
+    ok == 0 || code == STILL_ACTIVE
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_running(pid: u32) -> bool {
+    extern "C" {
+        fn kill(pid: i32, signal: i32) -> i32;
+    }
+    let Ok(pid) = i32::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks the process exists; EPERM means it does but belongs to someone else.
+    let signalled = unsafe { kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().kind() == std::io::ErrorKind::PermissionDenied
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+fn process_running(_pid: u32) -> bool {
+    true
+}
+
+// Drawn underneath the body editor, so the editor's own background has to be
+// transparent (or absent) for it to show.
+fn paper_shapes(ui: &egui::Ui, paper: PaperStyle, rect: egui::Rect, text_top: f32, fill: Option<egui::Color32>) -> Vec<egui::Shape> {
+    let visuals = ui.visuals();
+    let fill = match paper {
+        PaperStyle::Plain => return Vec::new(),
+        PaperStyle::Lined => fill,
+        PaperStyle::Tinted if visuals.dark_mode => Some(egui::Color32::from_rgb(38, 35, 28)),
+        PaperStyle::Tinted => Some(egui::Color32::from_rgb(250, 245, 228)),
+    };
+
+    let mut shapes = Vec::new();
+    if let Some(fill) = fill {
+        shapes.push(egui::Shape::rect_filled(rect, visuals.widgets.inactive.corner_radius, fill));
+    }
+    if paper == PaperStyle::Lined {
+        let row_height = ui.fonts(|f| f.row_height(&egui::TextStyle::Body.resolve(ui.style())));
+        let stroke = egui::Stroke::new(1.0, visuals.text_color().gamma_multiply(0.12));
+        let mut y = text_top + row_height;
+        while row_height > 0.0 && y < rect.bottom() {
+            shapes.push(egui::Shape::hline(rect.x_range().shrink(4.0), y, stroke));
+            y += row_height;
         }
+    }
+    shapes
+}
+
+// White on black is 21:1, and the yellow accents and navy selection both stay above 12:1 against
+// their background, well past the WCAG AAA threshold of 7:1.
+fn high_contrast_visuals() -> egui::Visuals {
+    let white = egui::Color32::WHITE;
+    let black = egui::Color32::BLACK;
+    let accent = egui::Color32::from_rgb(255, 255, 0);
+
+    let mut visuals = egui::Visuals::dark();
+    visuals.override_text_color = Some(white);
+    visuals.panel_fill = black;
+    visuals.window_fill = black;
+    visuals.extreme_bg_color = black;
+    visuals.faint_bg_color = egui::Color32::from_gray(28);
+    visuals.code_bg_color = egui::Color32::from_gray(28);
+    visuals.window_stroke = egui::Stroke::new(2.0, white);
+    visuals.hyperlink_color = egui::Color32::from_rgb(0, 255, 255);
+    visuals.warn_fg_color = accent;
+    visuals.error_fg_color = egui::Color32::from_rgb(255, 110, 110);
+    visuals.selection.bg_fill = egui::Color32::from_rgb(0, 0, 140);
+    visuals.selection.stroke = egui::Stroke::new(2.0, accent);
 
-        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) && self.dragging.is_some() {
-            self.dragging = None;
-            self.drag_start_pos = None;
+    let widgets = &mut visuals.widgets;
+    for widget in [&mut widgets.noninteractive, &mut widgets.inactive, &mut widgets.hovered, &mut widgets.active, &mut widgets.open] {
+        widget.bg_stroke = egui::Stroke::new(1.5, white);
+        widget.fg_stroke = egui::Stroke::new(1.5, white);
+    }
+    widgets.noninteractive.bg_fill = black;
+    widgets.noninteractive.weak_bg_fill = black;
+    widgets.inactive.bg_fill = black;
+    widgets.inactive.weak_bg_fill = black;
+    widgets.hovered.bg_stroke = egui::Stroke::new(2.0, accent);
+    widgets.active.bg_stroke = egui::Stroke::new(2.0, accent);
+    widgets.open.bg_stroke = egui::Stroke::new(2.0, accent);
+    visuals
+}
+
+fn load_settings<P: AsRef<Path>>(path: P) -> Result<AppSettings, Box<dyn std::error::Error>> {
+    if !path.as_ref().exists() {
+        return Ok(AppSettings::default());
+    }
+    let data = fs::read_to_string(path)?;
+    let mut value: serde_json::Value = serde_json::from_str(&data)?;
+    if let Some(settings) = value.as_object_mut() {
+        if let Some(dark_mode) = settings.remove("dark_mode").and_then(|v| v.as_bool()) {
+            let theme = if dark_mode { ThemeMode::Dark } else { ThemeMode::Light };
+            settings.entry("theme").or_insert(serde_json::to_value(theme)?);
         }
     }
+    let settings: AppSettings = serde_json::from_value(value)?;
+    Ok(settings)
 }
 
-fn load_notes<P: AsRef<Path>>(path: P) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+fn save_settings<P: AsRef<Path>>(path: P, settings: &AppSettings) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(settings)?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+fn load_history<P: AsRef<Path>>(path: P) -> Result<NoteHistory, Box<dyn std::error::Error>> {
     if !path.as_ref().exists() {
-        return Ok(vec![]);
+        return Ok(NoteHistory::new());
     }
     let data = fs::read_to_string(path)?;
-    let notes: Vec<Note> = serde_json::from_str(&data)?;
-    Ok(notes)
+    let history: NoteHistory = serde_json::from_str(&data)?;
+    Ok(history)
 }
 
-fn save_notes<P: AsRef<Path>>(path: P, notes: &Vec<Note>) -> Result<(), Box<dyn std::error::Error>> {
-    let json = serde_json::to_string_pretty(notes)?;
+fn save_history<P: AsRef<Path>>(path: P, history: &NoteHistory) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string(history)?;
     fs::write(path, json)?;
     Ok(())
 }
 
-fn load_settings<P: AsRef<Path>>(path: P) -> Result<AppSettings, Box<dyn std::error::Error>> {
+fn load_session<P: AsRef<Path>>(path: P) -> Result<SessionState, Box<dyn std::error::Error>> {
     if !path.as_ref().exists() {
-        return Ok(AppSettings::default());
+        return Ok(SessionState::default());
     }
     let data = fs::read_to_string(path)?;
-    let settings: AppSettings = serde_json::from_str(&data)?;
-    Ok(settings)
+    let session: SessionState = serde_json::from_str(&data)?;
+    Ok(session)
 }
 
-fn save_settings<P: AsRef<Path>>(path: P, settings: &AppSettings) -> Result<(), Box<dyn std::error::Error>> {
-    let json = serde_json::to_string_pretty(settings)?;
+fn save_session<P: AsRef<Path>>(path: P, session: &SessionState) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(session)?;
     fs::write(path, json)?;
     Ok(())
 }
 
+fn note_link(id: u128) -> String {
+    format!("{}{:032x}", NOTE_LINK_PREFIX, id)
+}
+
+// Notes reach each other through pasted note links or [[Title]] references; a
+// note linking to itself doesn't count.
+fn referenced_note_ids(notes: &[Note]) -> std::collections::HashSet<u128> {
+    let by_title: std::collections::HashMap<String, u128> =
+        notes.iter().map(|n| (n.title.trim().to_lowercase(), n.id)).collect();
+    let mut ids = std::collections::HashSet::new();
+    for note in notes {
+        for (start, _) in note.body.match_indices(NOTE_LINK_PREFIX) {
+            let hex: String = note.body[start + NOTE_LINK_PREFIX.len()..]
+                .chars()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .collect();
+            if let Some(id) = u128::from_str_radix(&hex, 16).ok().filter(|&id| id != note.id) {
+                ids.insert(id);
+            }
+        }
+
+        let mut rest = note.body.as_str();
+        while let Some(start) = rest.find("[[") {
+            rest = &rest[start + 2..];
+            let Some(end) = rest.find("]]") else {
+                break;
+            };
+            let target = rest[..end].split('|').next().unwrap_or_default().trim().to_lowercase();
+            if let Some(&id) = by_title.get(&target).filter(|&&id| id != note.id) {
+                ids.insert(id);
+            }
+            rest = &rest[end + 2..];
+        }
+    }
+    ids
+}
+
+fn parse_note_link(link: &str) -> Option<u128> {
+    let id = link.strip_prefix(NOTE_LINK_PREFIX)?;
+    u128::from_str_radix(id.trim_end_matches('/'), 16).ok()
+}
+
+fn parse_shortcut(text: &str) -> Result<egui::KeyboardShortcut, String> {
+    let mut modifiers = egui::Modifiers::NONE;
+    let mut key = None;
+    for part in text.split('+').map(str::trim) {
+        if key.is_some() {
+            return Err("Put the key after the modifiers, e.g. Ctrl+Alt+N".to_owned());
+        }
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "cmd" | "command" => modifiers = modifiers.plus(egui::Modifiers::COMMAND),
+            "alt" | "option" => modifiers = modifiers.plus(egui::Modifiers::ALT),
+            "shift" => modifiers = modifiers.plus(egui::Modifiers::SHIFT),
+            _ => key = Some(egui::Key::from_name(part).ok_or_else(|| format!("Unknown key \"{}\"", part))?),
+        }
+    }
+    let key = key.ok_or("Add a key after the modifiers, e.g. Ctrl+Alt+N")?;
+    if !modifiers.command && !modifiers.alt {
+        return Err("Include Ctrl or Alt so the shortcut doesn't get in the way of typing".to_owned());
+    }
+
+    let shortcut = egui::KeyboardShortcut::new(modifiers, key);
+    let taken = SHORTCUTS.iter().find(|info| {
+        info.keys.iter().any(|keys| matches!(keys, ShortcutKeys::Shortcut(s) if *s == shortcut))
+    });
+    match taken {
+        Some(info) => Err(format!("Already used for \"{}\"", info.description)),
+        None => Ok(shortcut),
+    }
+}
+
+// Hands a capture request to the instance that already has the notes folder open.
+// Returns false when no other instance is running.
+fn forward_capture(notes_dir: &Path) -> bool {
+    let lock = notes_dir.join(".lock");
+    let held = lock.exists() && InstanceLock::holder(&lock).is_none_or(process_running);
+    if !held {
+        return false;
+    }
+    match fs::write(notes_dir.join(CAPTURE_REQUEST), "") {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Failed to reach the running instance: {}", e);
+            false
+        }
+    }
+}
+
+fn parse_launch_args(mut args: impl Iterator<Item = String>) -> Result<Option<LaunchAction>, String> {
+    let Some(arg) = args.next() else {
+        return Ok(None);
+    };
+    Ok(match arg.as_str() {
+        "--open" => args.next().map(LaunchAction::Open),
+        "--capture" => Some(LaunchAction::Capture),
+        link if link.starts_with("notes://") => match parse_note_link(link) {
+            Some(id) => Some(LaunchAction::OpenId(id)),
+            None => {
+                eprintln!("Invalid note link: {}", link);
+                None
+            }
+        },
+        flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+        path => Some(LaunchAction::Import(std::path::PathBuf::from(path))),
+    })
+}
+
 fn main() -> eframe::Result<()> {
-    let native_options = eframe::NativeOptions::default();
+    let launch_action = match parse_launch_args(std::env::args().skip(1)) {
+        Ok(action) => action,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let app_dir = get_app_dir();
+    if matches!(launch_action, Some(LaunchAction::Capture)) && forward_capture(&get_notes_dir(&app_dir)) {
+        return Ok(());
+    }
+    let session = load_session(get_session_path(&app_dir)).unwrap_or_default();
+
+    let mut viewport = egui::ViewportBuilder::default();
+    if let Some(window) = session.window {
+        viewport = viewport
+            .with_inner_size([window.width, window.height])
+            .with_position([window.x, window.y]);
+    }
+
+    let native_options = eframe::NativeOptions {
+        viewport,
+        ..Default::default()
+    };
 
     eframe::run_native(
         "Notes",
         native_options,
-        Box::new(|_cc| Ok(Box::new(NotesApp::default()))),
+        Box::new(|cc| {
+            cc.egui_ctx.options_mut(|o| o.zoom_with_keyboard = false);
+            let mut app = NotesApp::open(app_dir);
+            if let Some(action) = launch_action {
+                app.apply_launch_action(&cc.egui_ctx, action);
+            }
+            Ok(Box::new(app))
+        }),
     )
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("notes-test-{:032x}", rand::random::<u128>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn compact_storage_round_trips_and_is_smaller() {
+        let dir = temp_dir();
+        let mut notes = vec![Note::new(1), Note::new(2)];
+        notes[0].body = "line one\nline two".to_owned();
+        notes[1].tags = vec!["a".to_owned(), "b".to_owned()];
+
+        let mut pretty = NoteStore::new(dir.join("pretty"));
+        let mut compact = NoteStore::new(dir.join("compact"));
+        compact.compact = true;
+        pretty.save(&notes).unwrap();
+        compact.save(&notes).unwrap();
+
+        let size = |store: &NoteStore| fs::metadata(store.note_path(2)).unwrap().len();
+        assert!(size(&compact) < size(&pretty));
+        let loaded = NoteStore::new(dir.join("compact")).load().unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&notes).unwrap());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reset_to_defaults_turns_compact_storage_off() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.set_compact_storage(true);
+        app.reset_settings(&egui::Context::default());
+        assert!(!app.settings.compact_storage);
+        assert!(!app.store.compact);
+    }
+
+    #[test]
+    fn store_skips_unreadable_note_files() {
+        let dir = temp_dir();
+        let mut store = NoteStore::new(dir.clone());
+        let notes = vec![Note::new(1), Note::new(2), Note::new(3)];
+        store.save(&notes).unwrap();
+        fs::write(store.note_path(2), "{ not json").unwrap();
+
+        let mut loaded = NoteStore::new(dir.clone());
+        let notes = loaded.load().unwrap();
+        assert_eq!(notes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(loaded.skipped.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2]);
+
+        loaded.save(&notes[..1]).unwrap();
+        let index: NotesIndex = serde_json::from_str(&fs::read_to_string(loaded.index_path()).unwrap()).unwrap();
+        assert_eq!(index.order, vec![1, 2]);
+        assert!(loaded.note_path(2).exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn instance_lock_treats_unnamed_holder_as_live() {
+        let dir = temp_dir();
+        let path = dir.join(".lock");
+        fs::write(&path, "").unwrap();
+        assert!(matches!(InstanceLock::acquire(path.clone()), Err(None)));
+
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+        let lock = InstanceLock::acquire(path.clone()).ok().unwrap();
+        assert_eq!(InstanceLock::holder(&path), Some(std::process::id()));
+        drop(lock);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn store_reports_only_changed_bodies() {
+        let dir = temp_dir();
+        let mut store = NoteStore::new(dir.clone());
+        let mut notes = vec![Note::new(1), Note::new(2)];
+        notes[0].body = "first".to_owned();
+        store.save(&notes).unwrap();
+
+        let mut loaded = NoteStore::new(dir.clone());
+        let mut notes = loaded.load().unwrap();
+        assert!(loaded.save(&notes).unwrap().is_empty());
+
+        notes[1].tags.push("idea".to_owned());
+        assert!(loaded.save(&notes).unwrap().is_empty());
+
+        notes[0].body = "second".to_owned();
+        let changed = loaded.save(&notes).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0, 1);
+        assert_eq!(changed[0].1.as_ref().map(|v| v.body.as_str()), Some("first"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    struct TestApp {
+        app: NotesApp,
+        dir: std::path::PathBuf,
+    }
+
+    impl TestApp {
+        fn new() -> Self {
+            let dir = temp_dir();
+            Self { app: NotesApp::open(dir.clone()), dir }
+        }
+    }
+
+    impl Drop for TestApp {
+        fn drop(&mut self) {
+            self.app.instance_lock = None;
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn escape_undoes_one_thing_at_a_time() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.add_note();
+        assert!(app.escape_action().is_none());
+
+        app.search = "milk".to_owned();
+        assert_eq!(app.escape_action(), Some(EscapeAction::ClearSearch));
+        app.zen_mode = true;
+        assert_eq!(app.escape_action(), Some(EscapeAction::LeaveZenMode));
+        app.zen_mode = false;
+        app.notes[0].start_editing();
+        assert_eq!(app.escape_action(), Some(EscapeAction::CancelEdit));
+        app.dragging = Some(0);
+        assert_eq!(app.escape_action(), Some(EscapeAction::CancelDrag));
+        app.show_shortcuts = true;
+        assert!(app.escape_action().is_none());
+    }
+
+    #[test]
+    fn api_creates_and_lists_notes() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+
+        let (status, created) = app.api_response("POST", "/notes", r#"{"title":"Script","body":"a\r\nb","tags":["x"]}"#);
+        assert_eq!(status, 201);
+        let created: serde_json::Value = serde_json::from_str(&created).unwrap();
+        let id = created["id"].as_str().unwrap().to_owned();
+        assert_eq!(created["body"], "a\nb");
+        assert!(app.store.note_path(u128::from_str_radix(&id, 16).unwrap()).exists());
+
+        let (status, list) = app.api_response("GET", "/notes/", "");
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&list).unwrap()[0]["title"], "Script");
+        assert_eq!(app.api_response("GET", &format!("/notes/{}", id), "").0, 200);
+        assert_eq!(app.api_response("GET", "/notes/ffff", "").0, 404);
+        assert_eq!(app.api_response("DELETE", "/notes", "").0, 405);
+        assert_eq!(app.api_response("POST", "/notes", "not json").0, 400);
+    }
+
+    #[test]
+    fn finds_referenced_notes() {
+        let mut notes: Vec<Note> = (1..=6).map(Note::new).collect();
+        let titles = ["Index", "Recipes", "Travel", "Ideas", "Loner", "Linked"];
+        for (note, title) in notes.iter_mut().zip(titles) {
+            note.title = title.to_owned();
+        }
+        notes[0].body = format!("See [[recipes]], [[Travel|trips]] and {}{:032x}", NOTE_LINK_PREFIX, 6);
+        notes[3].body = "[[Ideas]] links to itself, [[Nowhere]] to nothing, and [[Loner is never closed".to_owned();
+
+        let ids = referenced_note_ids(&notes);
+        let mut ids: Vec<u128> = ids.into_iter().collect();
+        ids.sort();
+        assert_eq!(ids, vec![2, 3, 6]);
+    }
+
+    #[test]
+    fn expands_export_file_names() {
+        let mut note = Note::new(0xab);
+        note.title = "Plans: Q3/Q4?".to_owned();
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(export_file_name("{title}", &note), "Plans_ Q3_Q4_");
+        assert_eq!(export_file_name("{date}-{title}", &note), format!("{}-Plans_ Q3_Q4_", today));
+        assert_eq!(export_file_name("{id}", &note), format!("{:032x}", 0xab));
+        assert_eq!(export_file_name("  ", &note), "note");
+
+        note.title = "con".to_owned();
+        assert_eq!(export_file_name("{title}", &note), "con_");
+        note.title = "x".repeat(300);
+        assert_eq!(export_file_name("{title}", &note).len(), 200);
+    }
+
+    #[test]
+    fn bundles_round_trip_and_reject_unsafe_names() {
+        let dir = temp_dir();
+        let attachments = dir.join("attachments");
+        let relative = new_attachment_path(&attachments, 1, "photo.png").unwrap();
+        fs::write(attachments.join(&relative), [1, 2, 3]).unwrap();
+        let mut note = Note::new(1);
+        note.title = "Trip".to_owned();
+        note.body = format!("![photo]({})", relative);
+        note.attachments.push(relative);
+
+        let bundle = dir.join("Trip.zip");
+        fs::write(&bundle, export_bundle(&note, &attachments).unwrap()).unwrap();
+        let imported = import_bundle(&bundle, &attachments).unwrap();
+        assert_eq!(imported.title, "Trip");
+        assert_eq!(imported.attachments.len(), 1);
+        assert_eq!(fs::read(attachments.join(&imported.attachments[0])).unwrap(), [1, 2, 3]);
+        assert_eq!(imported.body.trim(), format!("![photo]({})", imported.attachments[0]));
+
+        for name in ["..\\..\\x.exe", "../x.exe", "C:x.exe", "sub/x.exe", ".."] {
+            assert_eq!(bundle_file_name(name), None, "{}", name);
+        }
+        let evil = zip::write(&[
+            zip::Entry { name: BUNDLE_NOTE.to_owned(), data: b"body".to_vec() },
+            zip::Entry { name: format!("{}ok.txt", BUNDLE_ATTACHMENTS), data: b"ok".to_vec() },
+            zip::Entry { name: format!("{}..\\..\\x.exe", BUNDLE_ATTACHMENTS), data: b"bad".to_vec() },
+        ])
+        .unwrap();
+        fs::write(&bundle, evil).unwrap();
+        let before = fs::read_dir(&attachments).unwrap().count();
+        assert!(import_bundle(&bundle, &attachments).is_err());
+        assert_eq!(fs::read_dir(&attachments).unwrap().count(), before);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failed_saves_back_off_until_one_succeeds() {
+        let mut test = TestApp::new();
+        let blocker = test.dir.join("blocker");
+        fs::write(&blocker, "a file, not a folder").unwrap();
+        let app = &mut test.app;
+        app.store = NoteStore::new(blocker.join("notes"));
+        app.add_note();
+
+        let mut waits = Vec::new();
+        for attempt in 1..=3 {
+            app.save_notes();
+            let failure = app.save_failure.as_ref().unwrap();
+            assert_eq!(failure.attempts, attempt);
+            waits.push(failure.retry_at.saturating_duration_since(std::time::Instant::now()));
+        }
+        assert!(waits[0] < waits[1] && waits[1] < waits[2]);
+        assert!(app.dirty);
+
+        // Auto-save waits for the retry time instead of trying every frame.
+        let ctx = egui::Context::default();
+        let _ = ctx.run(egui::RawInput { time: Some(100.0), ..Default::default() }, |ctx| app.auto_save(ctx));
+        assert_eq!(app.save_failure.as_ref().unwrap().attempts, 3);
+
+        app.store = NoteStore::new(test.dir.join("notes"));
+        app.save_notes();
+        assert!(app.save_failure.is_none());
+        assert!(!app.dirty);
+        assert_eq!(save_retry_delay(20, 60), std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn discard_all_reloads_saved_notes() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.settings.auto_save = false;
+        app.add_note();
+        app.notes[0].body = "saved".to_owned();
+        app.save_notes();
+
+        app.notes[0].body = "unsaved".to_owned();
+        app.add_note();
+        assert!(app.has_pending_changes());
+        app.discard_all_changes().unwrap();
+        assert_eq!(app.notes.len(), 1);
+        assert_eq!(app.notes[0].body, "saved");
+        assert!(!app.has_pending_changes());
+
+        fs::write(app.store.index_path(), "{ broken").unwrap();
+        app.add_note();
+        assert!(app.discard_all_changes().is_err());
+        assert_eq!(app.notes.len(), 2);
+        assert!(app.dirty);
+    }
+
+    #[test]
+    fn parses_xrandr_monitors() {
+        let primary = parse_xrandr_monitor(" 0: +*eDP-1 1920/344x1080/193+0+0  eDP-1");
+        let secondary = parse_xrandr_monitor(" 1: +HDMI-1 2560/597x1440/336+1920+0  HDMI-1");
+        assert_eq!(primary, Some(egui::Rect::from_min_size(egui::pos2(0.0, 0.0), egui::vec2(1920.0, 1080.0))));
+        assert_eq!(secondary, Some(egui::Rect::from_min_size(egui::pos2(1920.0, 0.0), egui::vec2(2560.0, 1440.0))));
+        assert_eq!(parse_xrandr_monitor("Monitors: 2"), None);
+    }
+
+    #[test]
+    fn migrates_v0_notes_file() {
+        let v0 = r#"[{"id": 7, "title": "Groceries", "body": "milk", "modified": 1700000000}]"#;
+        let notes = migrate_notes(serde_json::from_str(v0).unwrap()).unwrap();
+        assert_eq!(notes.len(), 1);
+        let note = &notes[0];
+        assert_eq!((note.id, note.title.as_str(), note.body.as_str()), (7, "Groceries", "milk"));
+        assert!(note.tags.is_empty());
+        assert_eq!(note.created, 1700000000);
+        assert!(!note.no_autosave && !note.pinned && !note.archived && !note.locked);
+        assert!(note.pinned_in.is_empty() && note.attachments.is_empty());
+        assert_eq!((note.icon.clone(), note.last_opened, note.expires_at), (None, 0, None));
+        assert!(note.paper == PaperStyle::Plain);
+    }
+
+    #[test]
+    fn editing_state_is_not_persisted() {
+        let mut note = Note::new(1);
+        note.editing = true;
+        note.backup = Some("before".to_owned());
+        let json = serde_json::to_value(&note).unwrap();
+        assert!(json.get("editing").is_none() && json.get("backup").is_none());
+
+        let mut stored = json;
+        stored["editing"] = serde_json::Value::Bool(true);
+        stored["backup"] = serde_json::Value::from("before");
+        let loaded: Note = serde_json::from_value(stored).unwrap();
+        assert!(!loaded.editing);
+        assert_eq!(loaded.backup, None);
+    }
+
+    #[test]
+    fn converts_crlf_bodies_when_migrating() {
+        assert_eq!(normalize_line_endings("a\r\nb\rc\n"), "a\nb\nc\n");
+
+        let mut note = serde_json::to_value(Note::new(1)).unwrap();
+        note["body"] = serde_json::Value::from("one\r\ntwo\r\n");
+        let v6 = serde_json::json!({ "version": 6, "notes": [note.clone()] });
+        assert_eq!(migrate_notes(v6).unwrap()[0].body, "one\ntwo\n");
+
+        let current = serde_json::json!({ "version": NOTES_SCHEMA_VERSION, "notes": [note] });
+        assert_eq!(migrate_notes(current).unwrap()[0].body, "one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn keeps_full_u128_ids_through_migration() {
+        let mut store = NoteStore::new(temp_dir());
+        let notes = vec![Note::new(u128::MAX - 1)];
+        store.save(&notes).unwrap();
+        let loaded = NoteStore::new(store.dir.clone()).load().unwrap();
+        assert_eq!(loaded[0].id, u128::MAX - 1);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn rejects_notes_file_from_a_newer_version() {
+        let file = serde_json::json!({ "version": NOTES_SCHEMA_VERSION + 1, "notes": [] });
+        assert!(migrate_notes(file).is_err());
+    }
+
+    #[test]
+    fn parses_quick_capture_shortcuts() {
+        let shortcut = parse_shortcut("Ctrl + Alt + N").unwrap();
+        assert_eq!(shortcut, egui::KeyboardShortcut::new(egui::Modifiers::COMMAND.plus(egui::Modifiers::ALT), egui::Key::N));
+        assert!(parse_shortcut("Shift+N").is_err());
+        assert!(parse_shortcut("Ctrl+Alt").is_err());
+        assert!(parse_shortcut("Ctrl+Banana").is_err());
+        assert!(parse_shortcut("Ctrl+D").unwrap_err().contains("daily"));
+    }
+
+    #[test]
+    fn rejects_unknown_launch_flags() {
+        let parse = |args: &[&str]| parse_launch_args(args.iter().map(|a| a.to_string()));
+        assert!(matches!(parse(&[]), Ok(None)));
+        assert!(matches!(parse(&["--open", "Ideas"]), Ok(Some(LaunchAction::Open(title))) if title == "Ideas"));
+        assert!(matches!(parse(&["notes.md"]), Ok(Some(LaunchAction::Import(_)))));
+        assert!(parse(&["--help"]).is_err());
+        assert!(parse(&["--opne", "Ideas"]).is_err());
+    }
+
+    #[test]
+    fn trims_trailing_whitespace_but_keeps_hard_breaks_and_final_newline() {
+        assert_eq!(trim_trailing_whitespace("one \t\ntwo  \n   \n\n\n"), "one\ntwo  \n");
+        assert_eq!(trim_trailing_whitespace("  indented\t\nlast"), "  indented\nlast");
+        assert_eq!(trim_trailing_whitespace("break   \nnext\n"), "break  \nnext\n");
+        assert_eq!(trim_trailing_whitespace("\n\n  \n"), "");
+    }
+
+    #[test]
+    fn archives_expired_notes_and_restoring_clears_the_date() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        let ctx = egui::Context::default();
+        for _ in 0..3 {
+            app.add_note();
+        }
+        let now = current_unix();
+        app.notes[0].expires_at = Some(now - 60);
+        app.notes[0].pinned = true;
+        app.notes[1].expires_at = Some(now + 3600);
+        app.notes[2].expires_at = Some(now - 60);
+        app.notes[2].start_editing();
+        app.dirty = false;
+
+        app.expire_notes(&ctx);
+        assert!(app.notes[0].archived && !app.notes[0].pinned);
+        assert!(app.notes[0].is_expired());
+        assert!(!app.notes[1].archived && !app.notes[1].is_expired());
+        assert!(!app.notes[2].archived, "notes being edited aren't archived under the user");
+        assert!(app.dirty);
+
+        app.apply_note_action(0, NoteAction::ToggleArchive);
+        assert!(!app.notes[0].archived);
+        assert_eq!(app.notes[0].expires_at, None);
+        app.expire_notes(&ctx);
+        assert!(!app.notes[0].archived);
+    }
+
+    #[test]
+    fn expiry_dates_start_at_local_midnight() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let start = Local.timestamp_opt(start_of_day(date) as i64, 0).unwrap();
+        assert_eq!((start.date_naive(), start.time()), (date, chrono::NaiveTime::MIN));
+        let next = start_of_day(date.succ_opt().unwrap());
+        assert!((23 * 3600..=25 * 3600).contains(&(next - start_of_day(date))));
+    }
+
+    #[test]
+    fn layout_presets_save_and_apply_every_field() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        let ctx = egui::Context::default();
+        app.sidebar_width = 420.0;
+        app.settings.notes_layout = NotesLayout::Grid;
+        app.settings.font_size = MAX_FONT_SIZE;
+        app.settings.layout_presets.clear();
+
+        app.layout_preset_name = "  ".to_owned();
+        app.save_layout_preset();
+        assert!(app.settings.layout_presets.is_empty());
+        app.layout_preset_name = " Writing ".to_owned();
+        app.save_layout_preset();
+        app.settings.font_size = MIN_FONT_SIZE;
+        app.layout_preset_name = "Writing".to_owned();
+        app.save_layout_preset();
+        assert_eq!(app.settings.layout_presets.len(), 1, "saving under the same name replaces the preset");
+        let writing = app.settings.layout_presets[0].clone();
+        assert_eq!((writing.name.as_str(), writing.font_size), ("Writing", MIN_FONT_SIZE));
+        assert!(app.layout_preset_active(&writing));
+
+        app.settings.notes_layout = NotesLayout::List;
+        app.settings.font_size = 20.0;
+        app.sidebar_width = 200.0;
+        assert!(!app.layout_preset_active(&writing));
+        app.settings_changed = false;
+        app.apply_layout_preset(&ctx, &writing);
+        assert_eq!(app.pending_sidebar_width, Some(420.0));
+        // The sidebar picks up the pending width on the next frame.
+        app.sidebar_width = 420.0;
+        assert!(app.layout_preset_active(&writing));
+        assert!(app.settings_changed);
+
+        let oversized = LayoutPreset { font_size: MAX_FONT_SIZE * 4.0, sidebar_width: 10.0, ..writing };
+        app.apply_layout_preset(&ctx, &oversized);
+        assert_eq!((app.settings.font_size, app.pending_sidebar_width), (MAX_FONT_SIZE, Some(150.0)));
+    }
+
+    #[test]
+    fn quick_capture_leaves_the_selected_note_alone() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        let ctx = egui::Context::default();
+        app.add_note();
+        app.notes[0].modified = 1;
+        app.quick_capture(&ctx);
+        assert_eq!(app.notes.len(), 2);
+        let old = app.notes.iter().find(|n| n.modified == 1);
+        assert!(old.is_some(), "an untouched note keeps its modified time");
+
+        app.pending_switch = Some(app.notes[0].id);
+        app.capture_checked_at = -CAPTURE_POLL_INTERVAL;
+        fs::write(app.store.dir.join(CAPTURE_REQUEST), "").unwrap();
+        app.poll_capture_requests(&ctx);
+        assert_eq!(app.notes.len(), 2, "captures wait until the dialog is closed");
+        app.pending_switch = None;
+        app.poll_capture_requests(&ctx);
+        assert_eq!(app.notes.len(), 3);
+    }
+
+    #[test]
+    fn new_note_from_quick_access_asks_before_leaving_an_edit() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        let ctx = egui::Context::default();
+        app.settings.auto_save = false;
+        app.settings.save_on_switch = false;
+        app.settings.confirm_switch_while_editing = true;
+        app.add_note();
+        app.notes[0].start_editing();
+        app.notes[0].body = "draft".to_owned();
+
+        app.quick_capture(&ctx);
+        assert!(app.pending_capture && app.dialog_open());
+        assert_eq!(app.notes.len(), 1);
+        assert!(app.notes[0].editing, "the edit isn't saved without asking");
+
+        app.pending_capture = false;
+        app.settings.save_on_switch = true;
+        app.quick_capture(&ctx);
+        assert_eq!(app.notes.len(), 2);
+        let draft = app.notes.iter().find(|n| n.body == "draft").unwrap();
+        assert!(!draft.editing);
+    }
+
+    #[test]
+    fn daily_note_waits_for_the_unsaved_changes_prompt() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        let ctx = egui::Context::default();
+        app.settings.auto_save = false;
+        app.settings.save_on_switch = false;
+        app.settings.confirm_switch_while_editing = true;
+        app.settings.daily_note_title = "Daily".to_owned();
+        app.add_note();
+        app.notes[0].start_editing();
+        app.notes[0].body = "draft".to_owned();
+
+        app.open_daily_note(&ctx);
+        assert_eq!(app.notes[0].title, "Daily");
+        assert_eq!(app.selected, Some(1), "the edited note stays selected");
+        assert!(app.notes[1].editing);
+        assert_eq!(app.pending_switch, Some(app.notes[0].id));
+
+        app.pending_switch = None;
+        app.settings.save_on_switch = true;
+        app.open_daily_note(&ctx);
+        assert_eq!(app.notes.len(), 2);
+        assert_eq!(app.selected, Some(0));
+        assert!(!app.notes[1].editing);
+    }
+
+    #[test]
+    fn note_actions_ask_before_leaving_an_edit() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.settings.auto_save = false;
+        app.settings.save_on_switch = false;
+        app.settings.confirm_switch_while_editing = true;
+        app.settings.new_note_position = NewNotePosition::Bottom;
+        app.add_note();
+        app.add_note();
+        app.notes[1].start_editing();
+        app.notes[1].body = "draft".to_owned();
+
+        app.apply_note_action(0, NoteAction::Edit);
+        assert_eq!(app.selected, Some(1));
+        assert!(!app.notes[0].editing);
+        assert_eq!(app.pending_switch, Some(app.notes[0].id));
+
+        app.pending_switch = None;
+        app.apply_note_action(0, NoteAction::Duplicate);
+        assert_eq!(app.notes.len(), 3);
+        assert_eq!(app.selected, Some(2), "the edited note moved down past the copy");
+        assert!(app.notes[2].editing);
+        assert_eq!(app.pending_switch, Some(app.notes[1].id));
+    }
+
+    #[test]
+    fn only_picked_notes_count_as_opened() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.settings.auto_save = false;
+        app.add_note();
+        app.add_note();
+        app.save_notes();
+        app.selected = Some(0);
+        app.save_last_opened();
+        assert!(app.notes.iter().all(|n| n.last_opened == 0));
+
+        app.notes[0].start_editing();
+        app.notes[0].body = "unsaved".to_owned();
+        app.pick_note(1);
+        assert!(app.notes[1].last_opened > 0);
+        assert!(!app.dirty);
+        app.save_last_opened();
+
+        let saved = NoteStore::new(app.store.dir.clone()).load().unwrap();
+        assert_eq!(saved[1].last_opened, app.notes[1].last_opened);
+        assert_eq!(saved[0].body, "", "opening a note doesn't save other edits");
+    }
+
+    #[test]
+    fn opening_notes_is_not_a_pending_change() {
+        let mut test = TestApp::new();
+        let app = &mut test.app;
+        app.settings.auto_save = false;
+        app.add_note();
+        app.add_note();
+        app.save_notes();
+        app.notes = app.store.load().unwrap();
+        app.selected = Some(0);
+        assert!(!app.has_pending_changes());
+
+        app.pick_note(1);
+        app.save_last_opened();
+        assert!(!app.has_pending_changes());
+
+        app.apply_note_action(1, NoteAction::TogglePin);
+        assert!(app.has_pending_changes());
+        app.apply_note_action(1, NoteAction::TogglePin);
+        assert!(!app.has_pending_changes(), "undoing a change leaves nothing to save");
+    }
+}
@@ -0,0 +1,275 @@
+use eframe::egui;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "class", "const", "continue", "def", "else", "enum", "false",
+    "False", "fn", "for", "from", "function", "if", "impl", "import", "in", "let", "loop", "match",
+    "mod", "mut", "new", "None", "null", "pub", "return", "self", "Self", "static", "struct",
+    "trait", "true", "True", "type", "use", "var", "where", "while",
+];
+
+pub enum Block {
+    Text(String),
+    Code { language: String, code: String },
+    Table { alignments: Vec<egui::Align>, header: Vec<String>, rows: Vec<Vec<String>> },
+    Preformatted(String),
+}
+
+pub fn parse_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+    let mut fence: Option<String> = None;
+    let all_lines: Vec<&str> = text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+    let mut i = 0;
+
+    while i < all_lines.len() {
+        let line = all_lines[i];
+        let trimmed = line.trim();
+        match &fence {
+            Some(language) if trimmed == "```" => {
+                blocks.push(Block::Code { language: language.clone(), code: lines.join("\n") });
+                lines.clear();
+                fence = None;
+            }
+            None if trimmed.starts_with("```") => {
+                if !lines.is_empty() {
+                    blocks.push(Block::Text(lines.join("\n")));
+                    lines.clear();
+                }
+                fence = Some(trimmed.trim_start_matches('`').trim().to_owned());
+            }
+            None if trimmed.contains('|') && all_lines.get(i + 1).is_some_and(|l| is_table_separator(l)) => {
+                if !lines.is_empty() {
+                    blocks.push(Block::Text(lines.join("\n")));
+                    lines.clear();
+                }
+                let end = all_lines[i + 2..]
+                    .iter()
+                    .position(|l| !l.contains('|'))
+                    .map_or(all_lines.len(), |n| i + 2 + n);
+                blocks.push(parse_table(&all_lines[i..end]));
+                i = end;
+                continue;
+            }
+            _ => lines.push(line),
+        }
+        i += 1;
+    }
+
+    match fence {
+        Some(language) => blocks.push(Block::Code { language, code: lines.join("\n") }),
+        None if !lines.is_empty() => blocks.push(Block::Text(lines.join("\n"))),
+        None => {}
+    }
+    blocks
+}
+
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(|cell| cell.trim().to_owned()).collect()
+}
+
+fn is_table_separator(line: &str) -> bool {
+    line.contains('|')
+        && split_row(line).iter().all(|cell| {
+            let dashes = cell.strip_prefix(':').unwrap_or(cell);
+            let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn parse_table(lines: &[&str]) -> Block {
+    let header = split_row(lines[0]);
+    let alignments: Vec<egui::Align> = split_row(lines[1])
+        .iter()
+        .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => egui::Align::Center,
+            (false, true) => egui::Align::Max,
+            _ => egui::Align::Min,
+        })
+        .collect();
+    let mut rows: Vec<Vec<String>> = lines[2..].iter().map(|line| split_row(line)).collect();
+
+    if header.len() != alignments.len() || rows.iter().any(|row| row.len() > header.len()) {
+        return Block::Preformatted(lines.join("\n"));
+    }
+    for row in &mut rows {
+        row.resize(header.len(), String::new());
+    }
+    Block::Table { alignments, header, rows }
+}
+
+pub fn show(ui: &mut egui::Ui, text: &str) {
+    for (index, block) in parse_blocks(text).into_iter().enumerate() {
+        match block {
+            Block::Text(text) => {
+                ui.add(egui::Label::new(text).selectable(true));
+            }
+            Block::Code { language, code } => show_code_block(ui, &language, &code),
+            Block::Table { alignments, header, rows } => show_table(ui, index, &alignments, &header, &rows),
+            Block::Preformatted(text) => {
+                ui.add(egui::Label::new(egui::RichText::new(text).monospace()).selectable(true));
+            }
+        }
+    }
+}
+
+fn show_table(ui: &mut egui::Ui, index: usize, alignments: &[egui::Align], header: &[String], rows: &[Vec<String>]) {
+    let cell = |ui: &mut egui::Ui, align: egui::Align, text: egui::RichText| {
+        ui.with_layout(egui::Layout::top_down(align), |ui| {
+            ui.add(egui::Label::new(text).selectable(true));
+        });
+    };
+
+    egui::Frame::default()
+        .stroke(ui.visuals().widgets.noninteractive.bg_stroke)
+        .corner_radius(4.0)
+        .inner_margin(egui::Margin::same(4))
+        .show(ui, |ui| {
+            egui::Grid::new(ui.id().with(("markdown_table", index)))
+                .striped(true)
+                .num_columns(header.len())
+                .spacing(egui::vec2(12.0, 4.0))
+                .show(ui, |ui| {
+                    for (text, &align) in header.iter().zip(alignments) {
+                        cell(ui, align, egui::RichText::new(text).strong());
+                    }
+                    ui.end_row();
+                    for row in rows {
+                        for (text, &align) in row.iter().zip(alignments) {
+                            cell(ui, align, egui::RichText::new(text));
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+fn show_code_block(ui: &mut egui::Ui, language: &str, code: &str) {
+    egui::Frame::default()
+        .fill(ui.visuals().extreme_bg_color)
+        .stroke(ui.visuals().widgets.noninteractive.bg_stroke)
+        .corner_radius(4.0)
+        .inner_margin(egui::Margin::same(8))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            if !language.is_empty() {
+                ui.label(egui::RichText::new(language).size(10.0).weak());
+            }
+            let font_size = egui::TextStyle::Body.resolve(ui.style()).size;
+            ui.add(egui::Label::new(highlight(ui, language, code, font_size)).selectable(true));
+        });
+}
+
+fn highlight(ui: &egui::Ui, language: &str, code: &str, font_size: f32) -> egui::text::LayoutJob {
+    let visuals = ui.visuals();
+    let (keyword, string, comment, number) = if visuals.dark_mode {
+        (
+            egui::Color32::from_rgb(198, 120, 221),
+            egui::Color32::from_rgb(152, 195, 121),
+            egui::Color32::from_rgb(127, 132, 142),
+            egui::Color32::from_rgb(209, 154, 102),
+        )
+    } else {
+        (
+            egui::Color32::from_rgb(166, 38, 164),
+            egui::Color32::from_rgb(80, 161, 79),
+            egui::Color32::from_rgb(160, 161, 167),
+            egui::Color32::from_rgb(152, 104, 1),
+        )
+    };
+    let plain = visuals.text_color();
+    let comment_prefix = match language {
+        "python" | "py" | "sh" | "bash" | "shell" | "toml" | "yaml" | "yml" | "ruby" | "rb" => "#",
+        "sql" | "lua" => "--",
+        _ => "//",
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut append = |text: &str, color: egui::Color32| {
+        job.append(text, 0.0, egui::TextFormat::simple(egui::FontId::monospace(font_size), color));
+    };
+
+    for (line_idx, line) in code.split('\n').enumerate() {
+        if line_idx > 0 {
+            append("\n", plain);
+        }
+
+        let mut rest = line;
+        while !rest.is_empty() {
+            if rest.starts_with(comment_prefix) {
+                append(rest, comment);
+                break;
+            }
+
+            let first = rest.chars().next().unwrap_or_default();
+            let len = if first == '"' || first == '\'' {
+                let mut escaped = false;
+                let end = rest[1..]
+                    .char_indices()
+                    .find(|&(_, c)| {
+                        let close = c == first && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        close
+                    })
+                    .map(|(i, c)| i + 1 + c.len_utf8())
+                    .unwrap_or(rest.len());
+                append(&rest[..end], string);
+                end
+            } else if first.is_alphanumeric() || first == '_' {
+                let end = rest
+                    .char_indices()
+                    .find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+                    .map(|(i, _)| i)
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                let color = if KEYWORDS.contains(&word) {
+                    keyword
+                } else if first.is_ascii_digit() {
+                    number
+                } else {
+                    plain
+                };
+                append(word, color);
+                end
+            } else {
+                let end = first.len_utf8();
+                append(&rest[..end], plain);
+                end
+            };
+            rest = &rest[len..];
+        }
+    }
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered_rows(text: &str) -> usize {
+        let ctx = egui::Context::default();
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(2000.0, 2000.0))),
+            ..Default::default()
+        };
+        let output = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| show(ui, text));
+        });
+        output
+            .shapes
+            .iter()
+            .map(|clipped| match &clipped.shape {
+                egui::Shape::Text(shape) => shape.galley.rows.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn keeps_blank_lines() {
+        let body = "First paragraph\n\n\nSecond, after two blank lines\n\nThird";
+        assert_eq!(rendered_rows(body), body.lines().count());
+    }
+}
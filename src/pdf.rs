@@ -0,0 +1,440 @@
+use crate::markdown::{self, Block};
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const HEADER_SIZE: f32 = 9.0;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.5;
+const LINE_SPACING: f32 = 1.35;
+
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667,
+    556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556,
+    556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722,
+    500, 500, 500, 334, 260, 334, 584,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+    Mono,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Mono => "F3",
+        }
+    }
+
+    fn char_width(self, c: char, size: f32) -> f32 {
+        let width = match self {
+            Font::Mono => 600.0,
+            Font::Regular | Font::Bold => {
+                let base = match c as u32 {
+                    code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as f32,
+                    _ => 556.0,
+                };
+                if self == Font::Bold { base * 1.1 } else { base }
+            }
+        };
+        width * size / 1000.0
+    }
+
+    fn text_width(self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c, size)).sum()
+    }
+}
+
+struct Line {
+    font: Font,
+    size: f32,
+    indent: f32,
+    text: String,
+    space_before: f32,
+}
+
+pub fn render(title: &str, body: &str) -> Vec<u8> {
+    let width = PAGE_WIDTH - MARGIN * 2.0;
+    let mut lines = Vec::new();
+    push_wrapped(&mut lines, Font::Bold, 20.0, 0.0, 0.0, title, width);
+
+    for block in markdown::parse_blocks(body) {
+        match block {
+            Block::Text(text) => {
+                for line in text.split('\n') {
+                    push_text_line(&mut lines, line, width);
+                }
+            }
+            Block::Code { code, .. } => {
+                for (i, line) in code.split('\n').enumerate() {
+                    let space_before = if i == 0 { 6.0 } else { 0.0 };
+                    push_hard_wrapped(&mut lines, line, space_before, width);
+                }
+            }
+            Block::Table { header, rows, .. } => {
+                let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+                for row in &rows {
+                    for (w, cell) in widths.iter_mut().zip(row) {
+                        *w = (*w).max(cell.chars().count());
+                    }
+                }
+                let format_row = |cells: &[String]| {
+                    cells
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, &w)| format!("{:<w$}", cell, w = w))
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                };
+                push_hard_wrapped(&mut lines, &format_row(&header), 6.0, width);
+                let rule = widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>().join("-+-");
+                push_hard_wrapped(&mut lines, &rule, 0.0, width);
+                for row in &rows {
+                    push_hard_wrapped(&mut lines, &format_row(row), 0.0, width);
+                }
+            }
+            Block::Preformatted(text) => {
+                for line in text.split('\n') {
+                    push_hard_wrapped(&mut lines, line, 0.0, width);
+                }
+            }
+        }
+    }
+
+    let pages = paginate(lines);
+    write_document(title, &pages)
+}
+
+fn push_text_line(lines: &mut Vec<Line>, line: &str, width: f32) {
+    let trimmed = line.trim_start();
+    let heading_level = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&heading_level) && trimmed[heading_level..].starts_with(' ') {
+        let size = match heading_level {
+            1 => 17.0,
+            2 => 15.0,
+            3 => 13.0,
+            _ => BODY_SIZE,
+        };
+        let text = strip_emphasis(trimmed[heading_level..].trim());
+        push_wrapped(lines, Font::Bold, size, 0.0, 8.0, &text, width);
+        return;
+    }
+
+    let indent = (line.len() - trimmed.len()) as f32 * Font::Regular.char_width(' ', BODY_SIZE);
+    let bullet = ["- ", "* ", "+ "].iter().find_map(|prefix| trimmed.strip_prefix(prefix));
+    match bullet {
+        Some(rest) => {
+            let bullet_indent = indent + Font::Regular.text_width("• ", BODY_SIZE);
+            let start = lines.len();
+            push_wrapped(lines, Font::Regular, BODY_SIZE, bullet_indent, 0.0, &strip_emphasis(rest), width);
+            if let Some(first) = lines.get_mut(start) {
+                first.indent = indent;
+                first.text.insert_str(0, "• ");
+            }
+        }
+        None if trimmed.is_empty() => lines.push(Line {
+            font: Font::Regular,
+            size: BODY_SIZE,
+            indent: 0.0,
+            text: String::new(),
+            space_before: 0.0,
+        }),
+        None => push_wrapped(lines, Font::Regular, BODY_SIZE, indent, 0.0, &strip_emphasis(trimmed), width),
+    }
+}
+
+fn strip_emphasis(text: &str) -> String {
+    let text = text.replace("**", "").replace("__", "").replace('`', "");
+    let chars: Vec<char> = text.chars().collect();
+    let boundary = |c: Option<&char>| c.is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation());
+    let content = |c: Option<&char>| c.is_some_and(|c| !c.is_whitespace());
+    // A single * or _ only marks emphasis where it opens or closes a run of text,
+    // which leaves snake_case names and "2 * 3" alone.
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            let (before, after) = (i.checked_sub(1).and_then(|i| chars.get(i)), chars.get(i + 1));
+            let marker = (c == '*' || c == '_')
+                && ((boundary(before) && content(after)) || (content(before) && boundary(after)));
+            !marker
+        })
+        .map(|(_, &c)| c)
+        .collect()
+}
+
+// Cuts text down to `width`, ending it with an ellipsis when anything was dropped.
+fn fit_text(font: Font, size: f32, text: &str, width: f32) -> String {
+    if font.text_width(text, size) <= width {
+        return text.to_owned();
+    }
+    let mut used = font.char_width('…', size);
+    let mut fitted: String = text
+        .chars()
+        .take_while(|&c| {
+            used += font.char_width(c, size);
+            used <= width
+        })
+        .collect();
+    fitted.truncate(fitted.trim_end().len());
+    fitted.push('…');
+    fitted
+}
+
+fn push_wrapped(lines: &mut Vec<Line>, font: Font, size: f32, indent: f32, space_before: f32, text: &str, width: f32) {
+    let available = width - indent;
+    let mut current = String::new();
+    let mut first = true;
+    let mut flush = |lines: &mut Vec<Line>, text: String| {
+        lines.push(Line { font, size, indent, text, space_before: if first { space_before } else { 0.0 } });
+        first = false;
+    };
+
+    for word in text.split(' ') {
+        let candidate = if current.is_empty() { word.to_owned() } else { format!("{} {}", current, word) };
+        if font.text_width(&candidate, size) <= available {
+            current = candidate;
+            continue;
+        }
+        if !current.is_empty() {
+            flush(lines, std::mem::take(&mut current));
+        }
+        let mut word = word;
+        while font.text_width(word, size) > available {
+            let mut used = 0.0;
+            let split = word
+                .char_indices()
+                .find(|&(_, c)| {
+                    used += font.char_width(c, size);
+                    used > available
+                })
+                .map_or(word.len(), |(i, _)| i.max(word.chars().next().map_or(1, char::len_utf8)));
+            flush(lines, word[..split].to_owned());
+            word = &word[split..];
+        }
+        current = word.to_owned();
+    }
+    flush(lines, current);
+}
+
+fn push_hard_wrapped(lines: &mut Vec<Line>, text: &str, space_before: f32, width: f32) {
+    let per_line = ((width / Font::Mono.char_width(' ', CODE_SIZE)) as usize).max(1);
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        lines.push(Line { font: Font::Mono, size: CODE_SIZE, indent: 0.0, text: String::new(), space_before });
+        return;
+    }
+    for (i, chunk) in chars.chunks(per_line).enumerate() {
+        lines.push(Line {
+            font: Font::Mono,
+            size: CODE_SIZE,
+            indent: 0.0,
+            text: chunk.iter().collect(),
+            space_before: if i == 0 { space_before } else { 0.0 },
+        });
+    }
+}
+
+fn paginate(lines: Vec<Line>) -> Vec<Vec<(f32, Line)>> {
+    let top = PAGE_HEIGHT - MARGIN - HEADER_SIZE * 2.5;
+    let mut pages = vec![Vec::new()];
+    let mut y = top;
+
+    for line in lines {
+        let height = line.size * LINE_SPACING;
+        let mut next = y - line.space_before - height;
+        if next < MARGIN && !pages.last().is_some_and(Vec::is_empty) {
+            pages.push(Vec::new());
+            next = top - height;
+        }
+        y = next;
+        if let Some(page) = pages.last_mut() {
+            page.push((y, line));
+        }
+    }
+    pages
+}
+
+fn write_document(title: &str, pages: &[Vec<(f32, Line)>]) -> Vec<u8> {
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        Vec::new(),
+        font_object("Helvetica"),
+        font_object("Helvetica-Bold"),
+        font_object("Courier"),
+    ];
+
+    let mut kids = Vec::new();
+    for (number, page) in pages.iter().enumerate() {
+        let content = page_content(title, number + 1, pages.len(), page);
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(&content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+        let content_id = objects.len();
+
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, content_id
+            )
+            .into_bytes(),
+        );
+        kids.push(format!("{} 0 R", objects.len()));
+    }
+    objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).into_bytes();
+
+    let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(object);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(
+        format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes(),
+    );
+    out
+}
+
+fn font_object(name: &str) -> Vec<u8> {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", name).into_bytes()
+}
+
+fn page_content(title: &str, number: usize, count: usize, lines: &[(f32, Line)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let header_y = PAGE_HEIGHT - MARGIN;
+    let page_label = format!("Page {} of {}", number, count);
+    let label_x = PAGE_WIDTH - MARGIN - Font::Regular.text_width(&page_label, HEADER_SIZE);
+
+    out.extend_from_slice(b"0.4 g\n");
+    let title = fit_text(Font::Bold, HEADER_SIZE, title, label_x - MARGIN - HEADER_SIZE * 2.0);
+    show_text(&mut out, Font::Bold, HEADER_SIZE, MARGIN, header_y, &title);
+    show_text(&mut out, Font::Regular, HEADER_SIZE, label_x, header_y, &page_label);
+    out.extend_from_slice(
+        format!(
+            "0.4 G 0.5 w {} {} m {} {} l S\n0 g\n",
+            MARGIN,
+            header_y - HEADER_SIZE * 0.6,
+            PAGE_WIDTH - MARGIN,
+            header_y - HEADER_SIZE * 0.6
+        )
+        .as_bytes(),
+    );
+
+    for (y, line) in lines {
+        if !line.text.is_empty() {
+            show_text(&mut out, line.font, line.size, MARGIN + line.indent, *y, &line.text);
+        }
+    }
+    out
+}
+
+fn show_text(out: &mut Vec<u8>, font: Font, size: f32, x: f32, y: f32, text: &str) {
+    out.extend_from_slice(format!("BT /{} {} Tf {:.2} {:.2} Td (", font.resource(), size, x, y).as_bytes());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => out.extend_from_slice(&[b'\\', c as u8]),
+            _ => out.push(win_ansi(c)),
+        }
+    }
+    out.extend_from_slice(b") Tj ET\n");
+}
+
+fn win_ansi(c: char) -> u8 {
+    match c {
+        '\t' => b' ',
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_count(pdf: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(pdf);
+        let count = text.split("/Count ").nth(1).unwrap();
+        count[..count.find(' ').unwrap()].parse().unwrap()
+    }
+
+    #[test]
+    fn paginates_long_bodies() {
+        // The title takes the first line, leaving room for 45 body lines on page one and 47 after.
+        let body = |lines: usize| vec!["x"; lines].join("\n");
+        assert_eq!(page_count(&render("Title", "short")), 1);
+        assert_eq!(page_count(&render("Title", &body(92))), 2);
+        assert_eq!(page_count(&render("Title", &body(93))), 3);
+    }
+
+    #[test]
+    fn xref_offsets_point_at_objects() {
+        let pdf = render("Title", &"word ".repeat(2000));
+        let text = String::from_utf8_lossy(&pdf);
+        let xref = &text[text.rfind("\nxref\n").unwrap() + 1..];
+        let mut lines = xref.lines().skip(1);
+        let size: usize = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        let offsets: Vec<usize> = lines.skip(1).take(size - 1).map(|l| l[..10].parse().unwrap()).collect();
+        assert_eq!(offsets.len(), size - 1);
+        for (i, offset) in offsets.into_iter().enumerate() {
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()), "object {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn escapes_string_delimiters() {
+        let pdf = render("a (b) \\ c", "");
+        let needle = b"(a \\(b\\) \\\\ c) Tj";
+        assert!(pdf.windows(needle.len()).any(|w| w == needle));
+    }
+
+    #[test]
+    fn splits_words_wider_than_the_page() {
+        let width = 200.0;
+        let word = "w".repeat(100);
+        let mut lines = Vec::new();
+        push_wrapped(&mut lines, Font::Regular, BODY_SIZE, 0.0, 0.0, &word, width);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| Font::Regular.text_width(&l.text, BODY_SIZE) <= width));
+        assert_eq!(lines.iter().map(|l| l.text.as_str()).collect::<String>(), word);
+    }
+
+    #[test]
+    fn shortens_long_titles_in_the_header() {
+        let title = "Long title ".repeat(20);
+        let fitted = fit_text(Font::Bold, HEADER_SIZE, &title, 150.0);
+        assert!(fitted.ends_with('…') && title.starts_with(fitted.trim_end_matches('…')));
+        assert!(Font::Bold.text_width(&fitted, HEADER_SIZE) <= 150.0);
+        assert_eq!(fit_text(Font::Bold, HEADER_SIZE, "Short", 150.0), "Short");
+    }
+
+    #[test]
+    fn strips_emphasis_markers() {
+        assert_eq!(strip_emphasis("*one* _two_ **three** __four__ `five`"), "one two three four five");
+        assert_eq!(strip_emphasis("snake_case and 2 * 3"), "snake_case and 2 * 3");
+        assert_eq!(strip_emphasis("(*aside*)."), "(aside).");
+    }
+}
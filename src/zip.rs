@@ -0,0 +1,217 @@
+use chrono::{Datelike, Local, Timelike};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::{Read, Write};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+const VERSION: u16 = 20;
+const UTF8_NAMES: u16 = 0x0800;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const MAX_ENTRY_SIZE: usize = 256 * 1024 * 1024;
+const MAX_TOTAL_SIZE: usize = 1024 * 1024 * 1024;
+
+pub struct Entry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+pub fn write(entries: &[Entry]) -> std::io::Result<Vec<u8>> {
+    let now = Local::now();
+    let time = (now.hour() << 11 | now.minute() << 5 | (now.second() / 2)) as u16;
+    let date = (((now.year().clamp(1980, 2107) - 1980) as u32) << 9 | now.month() << 5 | now.day()) as u16;
+
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for entry in entries {
+        let mut crc = Crc::new();
+        crc.update(&entry.data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&entry.data)?;
+        let compressed = encoder.finish()?;
+        let (method, data) = if compressed.len() < entry.data.len() {
+            (DEFLATED, compressed.as_slice())
+        } else {
+            (STORED, entry.data.as_slice())
+        };
+        let offset = size_u32(out.len())?;
+        let fields = [VERSION, UTF8_NAMES, method, time, date];
+        let sizes = [crc.sum(), size_u32(data.len())?, size_u32(entry.data.len())?];
+        let name = entry.name.as_bytes();
+        let name_len = size_u16(name.len(), "file name")?;
+
+        put_u32(&mut out, LOCAL_HEADER);
+        fields.iter().for_each(|&v| put_u16(&mut out, v));
+        sizes.iter().for_each(|&v| put_u32(&mut out, v));
+        put_u16(&mut out, name_len);
+        put_u16(&mut out, 0);
+        out.extend_from_slice(name);
+        out.extend_from_slice(data);
+
+        put_u32(&mut directory, CENTRAL_HEADER);
+        put_u16(&mut directory, VERSION);
+        fields.iter().for_each(|&v| put_u16(&mut directory, v));
+        sizes.iter().for_each(|&v| put_u32(&mut directory, v));
+        put_u16(&mut directory, name_len);
+        // Extra field, comment, disk number and internal attributes.
+        [0, 0, 0, 0].iter().for_each(|&v| put_u16(&mut directory, v));
+        put_u32(&mut directory, 0);
+        put_u32(&mut directory, offset);
+        directory.extend_from_slice(name);
+    }
+
+    let count = size_u16(entries.len(), "number of entries")?;
+    let directory_offset = size_u32(out.len())?;
+    out.extend_from_slice(&directory);
+    put_u32(&mut out, END_OF_DIRECTORY);
+    put_u16(&mut out, 0);
+    put_u16(&mut out, 0);
+    put_u16(&mut out, count);
+    put_u16(&mut out, count);
+    put_u32(&mut out, size_u32(directory.len())?);
+    put_u32(&mut out, directory_offset);
+    put_u16(&mut out, 0);
+    Ok(out)
+}
+
+pub fn read(bytes: &[u8]) -> Result<Vec<Entry>, String> {
+    let end = (0..=bytes.len().saturating_sub(END_OF_DIRECTORY_SIZE))
+        .rev()
+        .find(|&i| get_u32(bytes, i) == Some(END_OF_DIRECTORY))
+        .ok_or("not a zip file")?;
+    let count = get_u16(bytes, end + 10).ok_or("truncated zip file")? as usize;
+    let mut position = get_u32(bytes, end + 16).ok_or("truncated zip file")? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut total = 0;
+    for _ in 0..count {
+        if get_u32(bytes, position) != Some(CENTRAL_HEADER) {
+            return Err("corrupt zip directory".to_owned());
+        }
+        let field = |offset: usize| get_u16(bytes, position + offset).ok_or("truncated zip file");
+        let method = field(10)?;
+        let crc = get_u32(bytes, position + 16).ok_or("truncated zip file")?;
+        let name_len = field(28)? as usize;
+        let extra_len = field(30)? as usize;
+        let comment_len = field(32)? as usize;
+        let compressed_size = get_u32(bytes, position + 20).ok_or("truncated zip file")? as usize;
+        let size = get_u32(bytes, position + 24).ok_or("truncated zip file")? as usize;
+        let header = get_u32(bytes, position + 42).ok_or("truncated zip file")? as usize;
+        let name = bytes.get(position + 46..position + 46 + name_len).ok_or("truncated zip file")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        position += 46 + name_len + extra_len + comment_len;
+
+        if get_u32(bytes, header) != Some(LOCAL_HEADER) {
+            return Err(format!("corrupt zip entry {}", name));
+        }
+        let local_name_len = get_u16(bytes, header + 26).ok_or("truncated zip file")? as usize;
+        let local_extra_len = get_u16(bytes, header + 28).ok_or("truncated zip file")? as usize;
+        let start = header + 30 + local_name_len + local_extra_len;
+        let raw = bytes.get(start..start + compressed_size).ok_or("truncated zip file")?;
+        total += size;
+        if size > MAX_ENTRY_SIZE || total > MAX_TOTAL_SIZE {
+            return Err(format!("{} is too large to import", name));
+        }
+        let data = match method {
+            STORED => raw.to_vec(),
+            DEFLATED => {
+                // One byte past the declared size is enough to tell the entry lied about it.
+                let mut data = Vec::with_capacity(size);
+                DeflateDecoder::new(raw)
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut data)
+                    .map_err(|e| format!("failed to inflate {}: {}", name, e))?;
+                data
+            }
+            _ => return Err(format!("{} uses an unsupported compression method", name)),
+        };
+        if data.len() != size {
+            return Err(format!("{} doesn't match its declared size", name));
+        }
+        let mut checksum = Crc::new();
+        checksum.update(&data);
+        if checksum.sum() != crc {
+            return Err(format!("{} is corrupt (checksum mismatch)", name));
+        }
+        entries.push(Entry { name, data });
+    }
+    Ok(entries)
+}
+
+fn size_u16(value: usize, what: &str) -> std::io::Result<u16> {
+    u16::try_from(value).map_err(|_| std::io::Error::other(format!("{} is too large for a zip archive", what)))
+}
+
+fn size_u32(size: usize) -> std::io::Result<u32> {
+    u32::try_from(size).map_err(|_| std::io::Error::other("zip archive is larger than 4 GB"))
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn get_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn get_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry { name: "note.md".to_owned(), data: b"# Title\n\n".repeat(50) },
+            Entry { name: "attachments/a.bin".to_owned(), data: vec![1, 2, 3] },
+        ]
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let read = read(&write(&entries()).unwrap()).unwrap();
+        let expected = entries();
+        assert_eq!(read.len(), expected.len());
+        for (read, expected) in read.iter().zip(&expected) {
+            assert_eq!((&read.name, &read.data), (&expected.name, &expected.data));
+        }
+    }
+
+    fn read_error(bytes: &[u8]) -> String {
+        read(bytes).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn rejects_corrupt_and_oversized_entries() {
+        let archive = write(&entries()).unwrap();
+        let directory = archive.windows(4).position(|w| w == CENTRAL_HEADER.to_le_bytes()).unwrap();
+
+        let mut corrupt = archive.clone();
+        corrupt[directory + 16] ^= 0xff;
+        assert!(read_error(&corrupt).contains("checksum"));
+
+        let mut lying = archive.clone();
+        lying[directory + 24..directory + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_error(&lying).contains("too large"));
+
+        let mut short = archive;
+        short[directory + 24..directory + 28].copy_from_slice(&10u32.to_le_bytes());
+        assert!(read_error(&short).contains("declared size"));
+    }
+
+    #[test]
+    fn refuses_names_that_do_not_fit() {
+        let long = Entry { name: "x".repeat(usize::from(u16::MAX) + 1), data: Vec::new() };
+        assert!(write(&[long]).is_err());
+    }
+}